@@ -6,11 +6,23 @@ use winit::{
 };
 use crate::camera::Camera;
 use crate::world::World;
-use crate::vertex::Vertex;
 use crate::uniforms::Uniforms;
-use crate::world_update::update_world;
+use crate::world_update::{update_world, view_distance};
 use crate::texture::Texture;
-use crate::event_loop::handle_event_loop;
+use crate::event_loop::{AppHandler, FrameState, handle_event_loop};
+use crate::indirect::ChunkArena;
+use crate::block::BlockRegistry;
+use crate::instancing::{InstancedCubes, create_instanced_pipeline};
+use crate::pipeline::{LightBindings, create_bind_group, create_bind_group_layout, create_render_pipeline, create_translucent_pipeline};
+use crate::hot_reload::HotReload;
+use crate::sky::{Sky, SkyConfig};
+use crate::day_night::WorldClock;
+use crate::shadow::ShadowMaps;
+use crate::render_targets::{RenderTargets, HDR_FORMAT, MSAA_FEATURES, clamp_sample_count, requested_sample_count, supported_sample_counts};
+use crate::post::{PostChain, PostConfig};
+use crate::ssao::Ssao;
+use crate::point_light::PointLights;
+use crate::present::{FramePacer, pick_present_mode, requested_fps_cap, requested_present_mode, supported_present_modes, supported_surface_formats};
 
 pub async fn run(event_loop: EventLoop<()>, window: Arc<Window>) {
     let size = window.inner_size();
@@ -26,19 +38,33 @@ pub async fn run(event_loop: EventLoop<()>, window: Arc<Window>) {
         force_fallback_adapter: false,
     }).await.unwrap();
 
+    // Draw every chunk with one indirect call where the adapter allows it, and allow every MSAA level it supports
+    let required_features = adapter.features() & (wgpu::Features::MULTI_DRAW_INDIRECT | MSAA_FEATURES);
+
     let (device, queue) = adapter
-        .request_device(&wgpu::DeviceDescriptor::default(), None)
+        .request_device(&wgpu::DeviceDescriptor {
+            required_features,
+            ..Default::default()
+        }, None)
         .await
         .unwrap();
 
-    let swapchain_format = wgpu::TextureFormat::Bgra8Unorm;
+    let capabilities = surface.get_capabilities(&adapter);
+    let present_modes = supported_present_modes(&capabilities);
+    let surface_formats = supported_surface_formats(&capabilities);
+    let swapchain_format = *surface_formats.first().expect("Surface has no usable formats");
+    let present_mode = pick_present_mode(requested_present_mode(), &present_modes);
+    println!("Present mode: {:?} (supported: {:?})", present_mode, present_modes);
+    println!("Surface format: {:?} (supported: {:?})", swapchain_format, surface_formats);
+
+    let frame_pacer = FramePacer::new(requested_fps_cap());
 
     let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: swapchain_format,
         width: size.width,
         height: size.height,
-        present_mode: wgpu::PresentMode::Mailbox,
+        present_mode,
         alpha_mode: wgpu::CompositeAlphaMode::Auto,
         desired_maximum_frame_latency: 1,
         view_formats: vec![],
@@ -46,94 +72,51 @@ pub async fn run(event_loop: EventLoop<()>, window: Arc<Window>) {
 
     surface.configure(&device, &config);
 
+    let sample_counts = supported_sample_counts(&adapter, &device);
+    let sample_count = clamp_sample_count(requested_sample_count(), &sample_counts);
+    println!("MSAA: {}x (supported: {:?})", sample_count, sample_counts);
+
     let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
         label: Some("Shader"),
         source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
     });
 
-    let texture = Texture::from_images(&device, &queue, [
-        "src/images/pos_x.png",
-        "src/images/neg_y.png",
-        "src/images/pos_y.png",
-        "src/images/neg_z.png",
-        "src/images/pos_z.png",
-        "src/images/neg_x.png",
-    ]);
+    let registry = Arc::new(BlockRegistry::load("src/blocks.toml").expect("Failed to load block registry"));
+    let texture_paths: Vec<&str> = registry.texture_paths.iter().map(|path| path.as_str()).collect();
+    let texture = Texture::from_layers(&device, &queue, &texture_paths, &registry.texture_cutout);
+
+    let block_face_layer_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Block Face Layer Buffer"),
+        contents: bytemuck::cast_slice(&registry.face_layer_table()),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
 
-    let uniforms = Uniforms::new();
+    let mut uniforms = Uniforms::new();
     let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Uniform Buffer"),
         contents: bytemuck::cast_slice(&[uniforms]),
         usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
-    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[ 
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Texture {
-                    multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::Cube,
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 2,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                count: None,
-            },
-        ],
-        label: Some("uniform_texture_bind_group_layout"),
-    });
+    let camera = Camera::new();
+    let mut world = World::new(8, registry.clone());
+    let mut chunk_arena = ChunkArena::new(&device, world.chunk_size);
 
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &bind_group_layout,
-        entries: &[ 
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::TextureView(&texture.view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: wgpu::BindingResource::Sampler(&texture.sampler),
-            },
-        ],
-        label: Some("uniform_texture_bind_group"),
-    });
+    let bind_group_layout = create_bind_group_layout(&device);
 
-    let depth_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("Depth Texture"),
-        size: wgpu::Extent3d {
-            width: config.width,
-            height: config.height,
-            depth_or_array_layers: 1,
-        },
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Depth32Float,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-        view_formats: &[],
-    });
+    let shadow_maps = ShadowMaps::new(&device, &shader);
+    let shadow_bind_group = shadow_maps.create_bind_group(&device, &uniform_buffer, &chunk_arena);
+    let point_lights = PointLights::new(&device);
+    let lights = LightBindings { shadow_maps: &shadow_maps, point_lights: &point_lights };
+    let bind_group = create_bind_group(&device, &bind_group_layout, &uniform_buffer, &texture, &chunk_arena, &block_face_layer_buffer, lights);
+
+    let render_targets = RenderTargets::new(&device, config.width, config.height, sample_count);
 
-    let depth_texture_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let post_config = PostConfig::load("src/post.toml").expect("Failed to load post-processing config");
+    let post_chain = PostChain::from_config(&device, &queue, &post_config, config.format, config.width, config.height)
+        .expect("Failed to create post-processing chain");
+    println!("Post-processing: {:?}", post_chain.effect_names());
+    let ssao = Ssao::new(&device, post_config.ssao, &render_targets, config.width, config.height);
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Render Pipeline Layout"),
@@ -141,104 +124,84 @@ pub async fn run(event_loop: EventLoop<()>, window: Arc<Window>) {
         push_constant_ranges: &[],
     });
 
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&pipeline_layout),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: Some("vs_main"),
-            buffers: &[wgpu::VertexBufferLayout {
-                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                step_mode: wgpu::VertexStepMode::Vertex,
-                attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2],
-            }],
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: Some("fs_main"),
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-            targets: &[Some(wgpu::ColorTargetState {
-                format: config.format,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-        }),
-        primitive: wgpu::PrimitiveState {
-            topology: wgpu::PrimitiveTopology::TriangleList,
-            strip_index_format: None,
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            ..Default::default()
-        },
-        depth_stencil: Some(wgpu::DepthStencilState {
-            format: wgpu::TextureFormat::Depth32Float,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState {
-                constant: 0,
-                slope_scale: 0.0,
-                clamp: 0.0,
-            },
-        }),
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-        cache: None, // Added missing field
-    });
-
-    let camera = Camera::new();
-    let mut world = World::new(8);
-    update_world(&camera, &mut world);
+    let render_pipeline = create_render_pipeline(&device, &pipeline_layout, &shader, HDR_FORMAT, sample_count);
+    let instanced_pipeline = create_instanced_pipeline(&device, &pipeline_layout, &shader, HDR_FORMAT, sample_count);
+    let translucent_pipeline = create_translucent_pipeline(&device, &pipeline_layout, &shader, HDR_FORMAT, sample_count);
+    let instanced_cubes = InstancedCubes::new(&device);
+    let hot_reload = HotReload::from_env(&registry);
 
-    let dynamic_vertex_buffer_size = 1024 * 1024 * std::mem::size_of::<Vertex>() as u64;
-    let dynamic_index_buffer_size = 1024 * 1024 * std::mem::size_of::<u16>() as u64;
+    let sky_config = SkyConfig::load("src/sky.toml").expect("Failed to load sky config");
+    let sky = Sky::new(&device, &queue, &sky_config, HDR_FORMAT, sample_count);
+    // Fog ends where the loaded chunks end, so nothing is ever seen popping in
+    uniforms.update_fog(&sky_config.fog, view_distance(world.chunk_size));
 
-    let dynamic_vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Dynamic Vertex Buffer"),
-        size: dynamic_vertex_buffer_size,
-        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+    // Start the morning, the clock then follows real time
+    let world_clock = WorldClock::new(9.0, sky_config.fog.color);
+    uniforms.update_lighting(&world_clock.sun_light());
 
-    let dynamic_index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("Dynamic Index Buffer"),
-        size: dynamic_index_buffer_size,
-        usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+    update_world(&camera, &mut world);
+    chunk_arena.sync(&queue, &world);
 
     // Wrap necessary arguments in Arc and Mutex
     let device = Arc::new(device);
     let queue = Arc::new(queue);
     let surface = Arc::new(surface);
     let config = Arc::new(Mutex::new(config));
+    let shader = Arc::new(shader);
     let bind_group = Arc::new(bind_group);
+    let bind_group_layout = Arc::new(bind_group_layout);
+    let pipeline_layout = Arc::new(pipeline_layout);
+    let block_face_layer_buffer = Arc::new(block_face_layer_buffer);
     let render_pipeline = Arc::new(render_pipeline);
-    let dynamic_vertex_buffer = Arc::new(Mutex::new(dynamic_vertex_buffer));
-    let dynamic_index_buffer = Arc::new(Mutex::new(dynamic_index_buffer));
+    let instanced_pipeline = Arc::new(instanced_pipeline);
+    let translucent_pipeline = Arc::new(translucent_pipeline);
+    let instanced_cubes = Arc::new(Mutex::new(instanced_cubes));
+    let sky = Arc::new(Mutex::new(sky));
+    let world_clock = Arc::new(Mutex::new(world_clock));
+    let shadow_maps = Arc::new(Mutex::new(shadow_maps));
+    let shadow_bind_group = Arc::new(shadow_bind_group);
+    let point_lights = Arc::new(Mutex::new(point_lights));
+    let chunk_arena = Arc::new(Mutex::new(chunk_arena));
     let uniform_buffer = Arc::new(uniform_buffer);
-    let depth_texture_view = Arc::new(depth_texture_view);
     let camera = Arc::new(Mutex::new(camera));
     let world = Arc::new(Mutex::new(world));
     let uniforms = Arc::new(Mutex::new(uniforms));
 
-    // Call the handle_event_loop function from the event_loop module
-    handle_event_loop(
-        event_loop,
-        window,
+    let app_handler = AppHandler {
         device,
         queue,
         surface,
         config,
+        shader,
         bind_group,
+        bind_group_layout,
+        pipeline_layout,
+        block_face_layer_buffer,
         render_pipeline,
-        dynamic_vertex_buffer,
-        dynamic_index_buffer,
+        instanced_pipeline,
+        translucent_pipeline,
+        chunk_arena,
+        instanced_cubes,
+        sky,
+        world_clock,
+        shadow_maps,
+        shadow_bind_group,
+        point_lights,
         uniform_buffer,
-        depth_texture_view,
+        render_targets,
+        sample_counts,
+        present_modes,
+        surface_formats,
+        frame_pacer,
+        post_chain,
+        ssao,
         camera,
         world,
         uniforms,
-    );
+        hot_reload,
+        window,
+        frame: FrameState::new(),
+    };
+
+    handle_event_loop(event_loop, app_handler);
 }
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+
+pub const AIR: u16 = 0;
+
+#[derive(Deserialize, Default)]
+struct FaceTextures {
+    all: Option<String>,
+    pos_x: Option<String>,
+    neg_x: Option<String>,
+    pos_y: Option<String>,
+    neg_y: Option<String>,
+    pos_z: Option<String>,
+    neg_z: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BlockEntry {
+    name: String,
+    id: u16,
+    #[serde(default)]
+    textures: FaceTextures,
+    solid: bool,
+    transparent: bool,
+    #[serde(default)]
+    cutout: bool,
+    #[serde(default)]
+    light_emission: u8,
+    #[serde(default)]
+    hardness: f32,
+}
+
+#[derive(Deserialize)]
+struct BlockFile {
+    block: Vec<BlockEntry>,
+}
+
+pub struct BlockDefinition {
+    pub solid: bool,
+    pub transparent: bool,
+    pub cutout: bool, // Alpha-tested faces, e.g. foliage
+    pub light_emission: u8,
+    pub hardness: f32,
+    pub face_layers: [u32; 6], // Texture array layer per face, in +X, -X, +Y, -Y, +Z, -Z order
+}
+
+pub struct BlockRegistry {
+    blocks: Vec<Option<BlockDefinition>>,
+    ids: HashMap<String, u16>,
+    pub texture_paths: Vec<String>,
+    pub texture_cutout: Vec<bool>, // Per texture layer, set when any cutout block uses it
+}
+
+impl BlockRegistry {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read block registry {}: {}", path, e))?;
+        Self::parse(&source).map_err(|e| format!("Failed to load block registry {}: {}", path, e))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let file: BlockFile = toml::from_str(source).map_err(|e| e.to_string())?;
+
+        let mut registry = Self {
+            blocks: Vec::new(),
+            ids: HashMap::new(),
+            texture_paths: Vec::new(),
+            texture_cutout: Vec::new(),
+        };
+
+        for entry in file.block {
+            if registry.ids.contains_key(&entry.name) {
+                return Err(format!("Duplicate block name {}", entry.name));
+            }
+            if registry.get(entry.id).is_some() {
+                return Err(format!("Duplicate block id {} for {}", entry.id, entry.name));
+            }
+
+            let textures = &entry.textures;
+            let faces = [&textures.pos_x, &textures.neg_x, &textures.pos_y, &textures.neg_y, &textures.pos_z, &textures.neg_z];
+            let mut face_layers = [0; 6];
+            for (face, texture) in faces.iter().enumerate() {
+                match texture.as_ref().or(textures.all.as_ref()) {
+                    Some(path) => face_layers[face] = registry.texture_layer(path, entry.cutout),
+                    None if entry.id != AIR => return Err(format!("Block {} is missing a texture for face {}", entry.name, face)),
+                    None => {}
+                }
+            }
+
+            let index = entry.id as usize;
+            if registry.blocks.len() <= index {
+                registry.blocks.resize_with(index + 1, || None);
+            }
+            registry.ids.insert(entry.name, entry.id);
+            registry.blocks[index] = Some(BlockDefinition {
+                solid: entry.solid,
+                transparent: entry.transparent,
+                cutout: entry.cutout,
+                light_emission: entry.light_emission,
+                hardness: entry.hardness,
+                face_layers,
+            });
+        }
+
+        if registry.get(AIR).is_none() {
+            return Err("Block id 0 must be defined as air".to_string());
+        }
+        if registry.texture_paths.is_empty() {
+            return Err("No block textures defined".to_string());
+        }
+
+        Ok(registry)
+    }
+
+    // Shared textures map to one layer no matter how many faces use them
+    fn texture_layer(&mut self, path: &str, cutout: bool) -> u32 {
+        match self.texture_paths.iter().position(|existing| existing == path) {
+            Some(layer) => {
+                self.texture_cutout[layer] |= cutout;
+                layer as u32
+            }
+            None => {
+                self.texture_paths.push(path.to_string());
+                self.texture_cutout.push(cutout);
+                (self.texture_paths.len() - 1) as u32
+            }
+        }
+    }
+
+    pub fn get(&self, id: u16) -> Option<&BlockDefinition> {
+        self.blocks.get(id as usize).and_then(|block| block.as_ref())
+    }
+
+    pub fn id(&self, name: &str) -> Option<u16> {
+        self.ids.get(name).copied()
+    }
+
+    // Unknown ids count as air so bad data never blocks the camera or the mesher
+    pub fn is_solid(&self, id: u16) -> bool {
+        self.get(id).is_some_and(|block| block.solid)
+    }
+
+    pub fn is_opaque(&self, id: u16) -> bool {
+        self.get(id).is_some_and(|block| block.solid && !block.transparent)
+    }
+
+    // Blended in the translucent pass (glass, water). Cutout blocks are alpha tested in the opaque pass instead.
+    pub fn is_translucent(&self, id: u16) -> bool {
+        self.get(id).is_some_and(|block| block.transparent && !block.cutout && id != AIR)
+    }
+
+    // Hardness from the data file, 0 for unknown ids
+    pub fn hardness(&self, id: u16) -> f32 {
+        self.get(id).map_or(0.0, |block| block.hardness)
+    }
+
+    pub fn face_layer(&self, id: u16, face: usize) -> u32 {
+        self.get(id).map_or(0, |block| block.face_layers[face])
+    }
+
+    // Six layers per block id, indexed by id * 6 + face in the shader
+    pub fn face_layer_table(&self) -> Vec<u32> {
+        (0..self.blocks.len())
+            .flat_map(|id| self.get(id as u16).map_or([0; 6], |block| block.face_layers))
+            .collect()
+    }
+}
@@ -0,0 +1,39 @@
+// fs_bright: params.values.x threshold
+// fs_blur: params.values.xy blur direction in texels
+// fs_composite: params.values.x intensity, the blurred bright pass is bound at binding 3
+
+@group(0) @binding(3)
+var bloom_texture: texture_2d<f32>;
+
+// Keeps what is brighter than the threshold, with a soft knee so highlights don't pop in
+@fragment
+fn fs_bright(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, input.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    let threshold = params.values.x;
+    let knee = clamp(brightness - threshold * 0.5, 0.0, threshold) / max(threshold, 0.0001);
+    let weight = max(brightness - threshold, 0.0) + knee * knee * threshold * 0.25;
+    return vec4<f32>(color * weight / max(brightness, 0.0001), 1.0);
+}
+
+const BLUR_WEIGHTS = array<f32, 5>(0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+// One direction of a separable 9-tap Gaussian
+@fragment
+fn fs_blur(input: VertexOutput) -> @location(0) vec4<f32> {
+    let step = params.values.xy / vec2<f32>(textureDimensions(input_texture));
+    var color = textureSample(input_texture, input_sampler, input.uv).rgb * BLUR_WEIGHTS[0];
+    for (var i = 1; i < 5; i++) {
+        let offset = step * f32(i);
+        color += textureSample(input_texture, input_sampler, input.uv + offset).rgb * BLUR_WEIGHTS[i];
+        color += textureSample(input_texture, input_sampler, input.uv - offset).rgb * BLUR_WEIGHTS[i];
+    }
+    return vec4<f32>(color, 1.0);
+}
+
+@fragment
+fn fs_composite(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, input.uv).rgb;
+    let bloom = textureSample(bloom_texture, input_sampler, input.uv).rgb;
+    return vec4<f32>(color + bloom * params.values.x, 1.0);
+}
@@ -1,4 +1,4 @@
-use cgmath::{Point3, Vector3, InnerSpace};
+use cgmath::{Matrix4, Point3, Rad, Vector3, Vector4, InnerSpace};
 
 pub struct Camera {
     pub eye: Point3<f32>,
@@ -69,4 +69,55 @@ impl Camera {
         self.eye += up * amount;
         self.target += up * amount;
     }
+
+    pub fn build_projection_matrix(&self) -> Matrix4<f32> {
+        cgmath::perspective(Rad(self.fovy), self.aspect, self.znear, self.zfar)
+    }
+
+    // The view is built with the eye at the origin, so world positions must be made relative to the eye first
+    pub fn build_view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_to_rh(Point3::new(0.0, 0.0, 0.0), self.target - self.eye, self.up)
+    }
+
+    pub fn build_view_projection_matrix(&self) -> Matrix4<f32> {
+        self.build_projection_matrix() * self.build_view_matrix()
+    }
+
+    // Only the rotation of the view is kept, so the sky stays infinitely far away however the camera moves
+    pub fn build_sky_view_projection_matrix(&self) -> Matrix4<f32> {
+        let mut view = self.build_view_matrix();
+        view.w = Vector4::new(0.0, 0.0, 0.0, 1.0);
+        self.build_projection_matrix() * view
+    }
+
+    // Splits the eye into its chunk and the offset inside that chunk, so large coordinates never reach the GPU
+    pub fn chunk_and_offset(&self, chunk_size: usize) -> ((i32, i32), [f32; 3]) {
+        let chunk_pos = (
+            (self.eye.x / (chunk_size as f32)).floor() as i32,
+            (self.eye.z / (chunk_size as f32)).floor() as i32,
+        );
+        let offset = [
+            self.eye.x - (chunk_pos.0 * chunk_size as i32) as f32,
+            self.eye.y,
+            self.eye.z - (chunk_pos.1 * chunk_size as i32) as f32,
+        ];
+        (chunk_pos, offset)
+    }
+
+    // Planes as (normal, distance) pointing into the camera-relative view volume, extracted from the view-projection rows
+    pub fn frustum_planes(&self) -> [[f32; 4]; 6] {
+        let m = self.build_view_projection_matrix();
+        let row = |i: usize| [m.x[i], m.y[i], m.z[i], m.w[i]];
+        let combine = |a: [f32; 4], b: [f32; 4], sign: f32| {
+            [a[0] + sign * b[0], a[1] + sign * b[1], a[2] + sign * b[2], a[3] + sign * b[3]]
+        };
+        [
+            combine(row(3), row(0), 1.0),
+            combine(row(3), row(0), -1.0),
+            combine(row(3), row(1), 1.0),
+            combine(row(3), row(1), -1.0),
+            combine(row(3), row(2), 1.0),
+            combine(row(3), row(2), -1.0),
+        ]
+    }
 }
@@ -1,40 +1,121 @@
-use crate::vertex::{Vertex, VERTICES, INDICES};
+use crate::vertex::{PackedVertex, FACE_CORNERS, FACE_INDICES, FLIPPED_FACE_INDICES, FACE_NORMALS};
+use crate::block::{BlockRegistry, AIR};
 
-pub fn generate_chunk_vertices(chunk_pos: (i32, i32), chunk_size: usize) -> Vec<Vertex> {
-    let mut vertices = Vec::new();
-    for x in 0..chunk_size {
+// Opaque and alpha-tested (cutout) faces are drawn together, translucent faces get their own blended pass
+#[derive(Default)]
+pub struct ChunkMesh {
+    pub vertices: Vec<PackedVertex>,
+    pub indices: Vec<u16>,
+    pub translucent_vertices: Vec<PackedVertex>,
+    pub translucent_indices: Vec<u16>, // Six per face, in the same face order as the vertices
+}
+
+// `light` returns the packed light and `opaque` whether the block is opaque at a chunk-local position,
+// which may lie in a neighbouring chunk
+pub fn generate_chunk_mesh(
+    blocks: &[u16],
+    chunk_size: usize,
+    registry: &BlockRegistry,
+    light: impl Fn([i32; 3]) -> u8,
+    opaque: impl Fn([i32; 3]) -> bool,
+) -> ChunkMesh {
+    let mut mesh = ChunkMesh::default();
+    for y in 0..CHUNK_HEIGHT {
         for z in 0..chunk_size {
-            let base_position = [
-                x as f32 + (chunk_pos.0 * chunk_size as i32) as f32,
-                0.0,
-                z as f32 + (chunk_pos.1 * chunk_size as i32) as f32,
-            ];
-            for vertex in VERTICES.iter() {
-                let mut position = vertex.position;
-                position[0] += base_position[0];
-                position[2] += base_position[2];
-
-                // Keep texture coordinates consistent across all chunks
-                let tex_coords = vertex.tex_coords;
-
-                vertices.push(Vertex {
-                    position,
-                    tex_coords,
-                });
+            for x in 0..chunk_size {
+                let block = blocks[block_index(x, y, z, chunk_size)];
+                if block == AIR {
+                    continue;
+                }
+                let translucent = registry.is_translucent(block);
+
+                // Positions stay local to the chunk, the shader adds the chunk origin
+                for (face, corners) in FACE_CORNERS.iter().enumerate() {
+                    // Faces pressed against an opaque block inside this chunk can never be seen,
+                    // and neither can the faces between two blocks of the same translucent type (water in water)
+                    let normal = FACE_NORMALS[face];
+                    let neighbour = [x as i32 + normal[0], y as i32 + normal[1], z as i32 + normal[2]];
+                    let neighbour_block = local_block(blocks, neighbour, chunk_size);
+                    if neighbour_block.is_some_and(|id| registry.is_opaque(id) || (translucent && id == block)) {
+                        continue;
+                    }
+
+                    let (vertices, indices) = if translucent {
+                        (&mut mesh.translucent_vertices, &mut mesh.translucent_indices)
+                    } else {
+                        (&mut mesh.vertices, &mut mesh.indices)
+                    };
+
+                    // A face is lit by the voxel in front of it
+                    let offset = vertices.len() as u16;
+                    let layer = registry.face_layer(block, face);
+                    let face_light = light(neighbour);
+                    let mut ao = [0; 4];
+                    for (corner, corner_offset) in corners.iter().enumerate() {
+                        ao[corner] = corner_ao(neighbour, normal, *corner_offset, &opaque);
+                        let position = [
+                            x as u32 + corner_offset[0],
+                            y as u32 + corner_offset[1],
+                            z as u32 + corner_offset[2],
+                        ];
+                        vertices.push(PackedVertex::new(position, face as u32, corner as u32, ao[corner], layer, face_light));
+                    }
+
+                    // Split the quad along the brighter diagonal, otherwise the AO gradient bends across the face
+                    let face_indices = if ao[0] + ao[2] < ao[1] + ao[3] { &FLIPPED_FACE_INDICES } else { &FACE_INDICES };
+                    indices.extend(face_indices.iter().map(|i| i + offset));
+                }
             }
         }
     }
-    vertices
+    mesh
 }
 
-pub fn generate_chunk_indices(chunk_size: usize) -> Vec<u16> {
-    let mut indices = Vec::new();
-    let vertex_count = INDICES.len() as u16;
+// Classic voxel AO: the two edge neighbours and the diagonal neighbour of a corner, in the layer of
+// voxels in front of the face. 3 is fully open, 0 is a corner boxed in by both edges.
+fn corner_ao(front: [i32; 3], normal: [i32; 3], corner: [u32; 3], opaque: &impl Fn([i32; 3]) -> bool) -> u32 {
+    let axis = normal.iter().position(|n| *n != 0).unwrap();
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+    // Step towards the side of the voxel the corner sits on
+    let mut side_u = front;
+    side_u[u] += if corner[u] == 1 { 1 } else { -1 };
+    let mut side_v = front;
+    side_v[v] += if corner[v] == 1 { 1 } else { -1 };
+    let mut diagonal = side_u;
+    diagonal[v] = side_v[v];
+
+    let (side_u, side_v, diagonal) = (opaque(side_u), opaque(side_v), opaque(diagonal));
+    if side_u && side_v {
+        return 0;
+    }
+    3 - (side_u as u32 + side_v as u32 + diagonal as u32)
+}
+
+pub const CHUNK_HEIGHT: usize = 16;
+
+pub fn block_index(x: usize, y: usize, z: usize, chunk_size: usize) -> usize {
+    (y * chunk_size + z) * chunk_size + x
+}
+
+// Block at a chunk-local position, or None when it falls outside the chunk
+pub fn local_block(blocks: &[u16], position: [i32; 3], chunk_size: usize) -> Option<u16> {
+    let [x, y, z] = position;
+    if x < 0 || y < 0 || z < 0 || x >= chunk_size as i32 || y >= CHUNK_HEIGHT as i32 || z >= chunk_size as i32 {
+        return None;
+    }
+    Some(blocks[block_index(x as usize, y as usize, z as usize, chunk_size)])
+}
+
+pub fn generate_chunk_blocks(chunk_size: usize, registry: &BlockRegistry) -> Vec<u16> {
+    let ground = registry.id("ground").expect("Block registry has no ground block");
+
+    // Only the ground layer is filled, everything above it is open air
+    let mut blocks = vec![AIR; chunk_size * CHUNK_HEIGHT * chunk_size];
     for x in 0..chunk_size {
         for z in 0..chunk_size {
-            let offset = ((x * chunk_size + z) * vertex_count as usize) as u16;
-            indices.extend(INDICES.iter().map(|i| i + offset));
+            blocks[block_index(x, 0, z, chunk_size)] = ground;
         }
     }
-    indices
+    blocks
 }
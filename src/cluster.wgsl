@@ -0,0 +1,82 @@
+// Bins point lights into view-space clusters, see point_light::PointLights.
+// The screen is split into CLUSTER_X by CLUSTER_Y tiles and the view depth into CLUSTER_Z slices
+// spaced exponentially between the near and far planes.
+
+const MAX_LIGHTS_PER_CLUSTER: u32 = 64u; // point_light::MAX_LIGHTS_PER_CLUSTER
+
+struct ClusterUniforms {
+    view: mat4x4<f32>, // Camera-relative, the eye is at the origin
+    inverse_projection: mat4x4<f32>,
+    grid: vec4<u32>, // Clusters along x, y and z, light count
+    params: vec4<f32>, // Near, far, screen width, screen height
+}
+
+struct PointLight {
+    position_radius: vec4<f32>, // Camera-relative position, radius
+    color_intensity: vec4<f32>,
+}
+
+struct ClusterLights {
+    count: u32,
+    indices: array<u32, MAX_LIGHTS_PER_CLUSTER>,
+}
+
+@group(0) @binding(0)
+var<uniform> cluster: ClusterUniforms;
+
+@group(0) @binding(1)
+var<storage, read> lights: array<PointLight>;
+
+@group(0) @binding(2)
+var<storage, read_write> clusters: array<ClusterLights>;
+
+// The view-space point on the ray through `ndc` at view depth `depth`
+fn point_at_depth(ndc: vec2<f32>, depth: f32) -> vec3<f32> {
+    let far = cluster.inverse_projection * vec4<f32>(ndc, 1.0, 1.0);
+    let direction = far.xyz / far.w;
+    return direction * (depth / -direction.z);
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let grid = cluster.grid.xyz;
+    let index = id.x;
+    if (index >= grid.x * grid.y * grid.z) {
+        return;
+    }
+    let x = index % grid.x;
+    let y = (index / grid.x) % grid.y;
+    let z = index / (grid.x * grid.y);
+
+    // Tile corners in NDC, y flipped since tiles count down from the top of the screen
+    let tile = vec2<f32>(f32(x), f32(y));
+    let ndc_min = vec2<f32>(tile.x / f32(grid.x) * 2.0 - 1.0, 1.0 - (tile.y + 1.0) / f32(grid.y) * 2.0);
+    let ndc_max = vec2<f32>((tile.x + 1.0) / f32(grid.x) * 2.0 - 1.0, 1.0 - tile.y / f32(grid.y) * 2.0);
+
+    let near = cluster.params.x;
+    let far = cluster.params.y;
+    let slice_near = near * pow(far / near, f32(z) / f32(grid.z));
+    let slice_far = near * pow(far / near, f32(z + 1u) / f32(grid.z));
+
+    var aabb_min = vec3<f32>(1e30);
+    var aabb_max = vec3<f32>(-1e30);
+    for (var corner = 0u; corner < 8u; corner++) {
+        let ndc = vec2<f32>(select(ndc_min.x, ndc_max.x, (corner & 1u) != 0u), select(ndc_min.y, ndc_max.y, (corner & 2u) != 0u));
+        let point = point_at_depth(ndc, select(slice_near, slice_far, (corner & 4u) != 0u));
+        aabb_min = min(aabb_min, point);
+        aabb_max = max(aabb_max, point);
+    }
+
+    var count = 0u;
+    for (var i = 0u; i < cluster.grid.w && count < MAX_LIGHTS_PER_CLUSTER; i++) {
+        let light = lights[i];
+        let center = (cluster.view * vec4<f32>(light.position_radius.xyz, 1.0)).xyz;
+        let closest = clamp(center, aabb_min, aabb_max);
+        let offset = closest - center;
+        if (dot(offset, offset) <= light.position_radius.w * light.position_radius.w) {
+            clusters[index].indices[count] = i;
+            count++;
+        }
+    }
+    clusters[index].count = count;
+}
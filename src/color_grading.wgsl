@@ -0,0 +1,15 @@
+// params.values.x: LUT size, params.values.y: strength
+// The LUT maps input colors in [0, 1] to graded colors, so it runs after tonemapping.
+
+@group(0) @binding(3)
+var lut_texture: texture_3d<f32>;
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = clamp(textureSample(input_texture, input_sampler, input.uv).rgb, vec3<f32>(0.0), vec3<f32>(1.0));
+    // Sample texel centers so the ends of the range land on the first and last entries
+    let size = params.values.x;
+    let lut_uv = color * ((size - 1.0) / size) + 0.5 / size;
+    let graded = textureSampleLevel(lut_texture, input_sampler, lut_uv, 0.0).rgb;
+    return vec4<f32>(mix(color, graded, params.values.y), 1.0);
+}
@@ -0,0 +1,70 @@
+struct CullUniforms {
+    planes: array<vec4<f32>, 6>,
+    camera_chunk: vec4<i32>,
+    camera_offset: vec4<f32>, // w holds the chunk size
+    chunk_count: u32,
+}
+
+struct ChunkDraw {
+    chunk_pos: vec4<i32>,
+    aabb_min: vec4<f32>,
+    aabb_max: vec4<f32>,
+    index_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    _padding: u32,
+}
+
+struct DrawIndexedIndirectArgs {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> cull: CullUniforms;
+
+@group(0) @binding(1)
+var<storage, read> chunks: array<ChunkDraw>;
+
+@group(0) @binding(2)
+var<storage, read> visibility: array<u32>; // Occlusion result written by the CPU each frame
+
+@group(0) @binding(3)
+var<storage, read_write> draws: array<DrawIndexedIndirectArgs>;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= cull.chunk_count) {
+        return;
+    }
+
+    let chunk = chunks[i];
+    var visible = chunk.index_count > 0u && visibility[i] != 0u;
+
+    // Bounds are local to the chunk, move them into the same camera-relative space as the planes
+    let chunk_delta = vec3<f32>(chunk.chunk_pos.xyz - cull.camera_chunk.xyz);
+    let shift = chunk_delta * cull.camera_offset.w - cull.camera_offset.xyz;
+    let aabb_min = chunk.aabb_min.xyz + shift;
+    let aabb_max = chunk.aabb_max.xyz + shift;
+
+    // Test the corner of the box furthest along each plane normal
+    for (var p = 0u; p < 6u; p++) {
+        let plane = cull.planes[p];
+        let corner = select(aabb_min, aabb_max, plane.xyz >= vec3<f32>(0.0));
+        if (dot(plane.xyz, corner) + plane.w < 0.0) {
+            visible = false;
+        }
+    }
+
+    draws[i] = DrawIndexedIndirectArgs(
+        chunk.index_count,
+        select(0u, 1u, visible),
+        chunk.first_index,
+        chunk.base_vertex,
+        0u,
+    );
+}
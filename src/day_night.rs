@@ -0,0 +1,107 @@
+use cgmath::{InnerSpace, Vector3};
+use crate::lighting::SunLight;
+
+pub const HOURS_PER_DAY: f32 = 24.0;
+// Real seconds for one full in-game day
+pub const DEFAULT_DAY_LENGTH: f32 = 600.0;
+
+// The sun's path is tilted off the x axis so block faces never light up identically
+const SUN_PATH_TILT: f32 = 0.3;
+
+const DAY_ZENITH: [f32; 3] = [0.25, 0.5, 0.9];
+const NIGHT_ZENITH: [f32; 3] = [0.01, 0.01, 0.04];
+const NIGHT_HORIZON: [f32; 3] = [0.04, 0.05, 0.09];
+const SUNSET_HORIZON: [f32; 3] = [0.9, 0.5, 0.3];
+
+const DAY_SUN: [f32; 3] = [0.75, 0.72, 0.65];
+const SUNSET_SUN: [f32; 3] = [0.8, 0.45, 0.25];
+const DAY_AMBIENT: [f32; 3] = [0.35, 0.38, 0.45];
+const NIGHT_AMBIENT: [f32; 3] = [0.05, 0.06, 0.1];
+
+// Sky colors for the gradient drawn by the sky pass, the horizon color is also the fog color
+#[derive(Copy, Clone)]
+pub struct SkyColors {
+    pub zenith: [f32; 3],
+    pub horizon: [f32; 3],
+}
+
+// In-game time of day, advanced with the real frame delta
+pub struct WorldClock {
+    time_of_day: f32, // Hours, 6 is sunrise, 12 noon and 18 sunset
+    pub day_length: f32, // Real seconds per in-game day
+    pub paused: bool,
+    day_horizon: [f32; 3],
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+impl WorldClock {
+    // `day_horizon` is the daytime horizon color, normally the fog color from sky.toml
+    pub fn new(time_of_day: f32, day_horizon: [f32; 3]) -> Self {
+        Self {
+            time_of_day: time_of_day.rem_euclid(HOURS_PER_DAY),
+            day_length: DEFAULT_DAY_LENGTH,
+            paused: false,
+            day_horizon,
+        }
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if self.paused || self.day_length <= 0.0 {
+            return;
+        }
+        self.time_of_day = (self.time_of_day + dt / self.day_length * HOURS_PER_DAY).rem_euclid(HOURS_PER_DAY);
+    }
+
+    pub fn time_of_day(&self) -> f32 {
+        self.time_of_day
+    }
+
+    pub fn set_time_of_day(&mut self, hours: f32) {
+        self.time_of_day = hours.rem_euclid(HOURS_PER_DAY);
+    }
+
+    // Points towards the sun, which rises in +X at 6:00 and sets in -X at 18:00
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let angle = (self.time_of_day - 6.0) / HOURS_PER_DAY * std::f32::consts::TAU;
+        Vector3::new(angle.cos(), angle.sin(), SUN_PATH_TILT).normalize()
+    }
+
+    // 1 in full daylight, 0 at night, with a short dawn and dusk around the horizon
+    fn daylight(&self) -> f32 {
+        smoothstep(-0.15, 0.2, self.sun_direction().y)
+    }
+
+    // Strongest while the sun is crossing the horizon
+    fn twilight(&self) -> f32 {
+        1.0 - smoothstep(0.0, 0.3, self.sun_direction().y.abs())
+    }
+
+    pub fn sun_light(&self) -> SunLight {
+        let daylight = self.daylight();
+        let sun_color = mix(DAY_SUN, SUNSET_SUN, self.twilight());
+        let direction = self.sun_direction();
+        SunLight {
+            direction,
+            // The sun stops lighting anything once it is below the horizon
+            color: mix([0.0; 3], sun_color, smoothstep(-0.05, 0.1, direction.y)),
+            ambient: mix(NIGHT_AMBIENT, DAY_AMBIENT, daylight),
+        }
+    }
+
+    pub fn sky_colors(&self) -> SkyColors {
+        let daylight = self.daylight();
+        let horizon = mix(mix(NIGHT_HORIZON, self.day_horizon, daylight), SUNSET_HORIZON, self.twilight() * 0.6);
+        SkyColors {
+            zenith: mix(NIGHT_ZENITH, DAY_ZENITH, daylight),
+            horizon,
+        }
+    }
+}
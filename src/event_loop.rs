@@ -9,37 +9,84 @@ use winit::{
     keyboard::{PhysicalKey, KeyCode}
 };
 use std::collections::HashSet;
+use cgmath::Point3;
 use std::sync::{Arc, Mutex};
 use crate::camera::Camera;
 use crate::world::World;
-use crate::vertex::Vertex;
 use crate::uniforms::Uniforms;
 use crate::world_update::update_world;
-use crate::texture::get_texture;
-use wgpu::util::DeviceExt;
-
-struct AppHandler<'a> {
-    surface: Arc<wgpu::Surface<'a>>,
-    device: Arc<wgpu::Device>,
-    queue: Arc<wgpu::Queue>,
-    config: Arc<Mutex<wgpu::SurfaceConfiguration>>,
-    bind_group: Arc<wgpu::BindGroup>,
-    render_pipeline: Arc<wgpu::RenderPipeline>,
-    dynamic_vertex_buffer: Arc<Mutex<wgpu::Buffer>>,
-    dynamic_index_buffer: Arc<Mutex<wgpu::Buffer>>,
-    uniform_buffer: Arc<wgpu::Buffer>,
-    depth_texture_view: Arc<wgpu::TextureView>,
-    camera: Arc<Mutex<Camera>>,
-    world: Arc<Mutex<World>>,
-    uniforms: Arc<Mutex<Uniforms>>,
+use crate::occlusion::visible_chunks;
+use crate::indirect::ChunkArena;
+use crate::instancing::InstancedCubes;
+use crate::sky::Sky;
+use crate::day_night::WorldClock;
+use crate::hot_reload::HotReload;
+use crate::pipeline::{LightBindings, create_bind_group, create_render_pipeline, create_translucent_pipeline};
+use crate::instancing::create_instanced_pipeline;
+use crate::shadow::{ShadowMaps, create_shadow_pipeline};
+use crate::world_update::view_distance;
+use crate::render_targets::{RenderTargets, HDR_FORMAT, clamp_sample_count, next_sample_count};
+use crate::post::PostChain;
+use crate::ssao::Ssao;
+use crate::point_light::PointLights;
+use crate::present::{FramePacer, next_present_mode, next_surface_format};
+
+// Everything the window's event loop renders with, set up by app::run
+pub struct AppHandler<'a> {
+    pub surface: Arc<wgpu::Surface<'a>>,
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    pub config: Arc<Mutex<wgpu::SurfaceConfiguration>>,
+    pub shader: Arc<wgpu::ShaderModule>,
+    pub bind_group: Arc<wgpu::BindGroup>,
+    pub bind_group_layout: Arc<wgpu::BindGroupLayout>,
+    pub pipeline_layout: Arc<wgpu::PipelineLayout>,
+    pub block_face_layer_buffer: Arc<wgpu::Buffer>,
+    pub render_pipeline: Arc<wgpu::RenderPipeline>,
+    pub instanced_pipeline: Arc<wgpu::RenderPipeline>,
+    pub translucent_pipeline: Arc<wgpu::RenderPipeline>,
+    pub chunk_arena: Arc<Mutex<ChunkArena>>,
+    pub instanced_cubes: Arc<Mutex<InstancedCubes>>,
+    pub sky: Arc<Mutex<Sky>>,
+    pub world_clock: Arc<Mutex<WorldClock>>,
+    pub shadow_maps: Arc<Mutex<ShadowMaps>>,
+    pub shadow_bind_group: Arc<wgpu::BindGroup>,
+    pub point_lights: Arc<Mutex<PointLights>>,
+    pub uniform_buffer: Arc<wgpu::Buffer>,
+    pub render_targets: RenderTargets,
+    pub sample_counts: Vec<u32>, // Supported MSAA levels
+    pub present_modes: Vec<wgpu::PresentMode>,
+    pub surface_formats: Vec<wgpu::TextureFormat>,
+    pub frame_pacer: FramePacer,
+    pub post_chain: PostChain,
+    pub ssao: Ssao,
+    pub camera: Arc<Mutex<Camera>>,
+    pub world: Arc<Mutex<World>>,
+    pub uniforms: Arc<Mutex<Uniforms>>,
+    pub window: Arc<winit::window::Window>,
+    pub hot_reload: Option<HotReload>,
+    pub frame: FrameState,
+}
+
+// Input and frame bookkeeping that only the event loop touches
+pub struct FrameState {
     last_frame_time: Arc<Mutex<std::time::Instant>>,
     pressed_keys: Arc<Mutex<HashSet<KeyCode>>>,
     log_frame_count: usize,
-    last_camera_position: [f32; 3],
-    movement_threshold: f32,
-    dynamic_vertex_buffer_size: Arc<Mutex<u64>>, // Added
-    dynamic_index_buffer_size: Arc<Mutex<u64>>,  // Added
-    window: Arc<winit::window::Window>,
+    last_camera_chunk: Option<(i32, i32)>, // The chunk update_world last loaded around
+    minimized: bool, // Nothing is rendered while the window has a zero size
+}
+
+impl FrameState {
+    pub fn new() -> Self {
+        Self {
+            last_frame_time: Arc::new(Mutex::new(std::time::Instant::now())),
+            pressed_keys: Arc::new(Mutex::new(HashSet::new())),
+            log_frame_count: 0,
+            last_camera_chunk: None,
+            minimized: false,
+        }
+    }
 }
 
 impl AppHandler<'_> {
@@ -48,22 +95,11 @@ impl AppHandler<'_> {
 
         match event {
             Event::WindowEvent { event, .. } => match event {
-                WindowEvent::Resized(new_size) => {
-                    if new_size.width > 0 && new_size.height > 0 {
-                        let mut config = self.config.lock().unwrap();
-                        config.width = new_size.width;
-                        config.height = new_size.height;
-                        let mut camera = self.camera.lock().unwrap();
-                        camera.aspect = config.width as f32 / config.height as f32;
-                        self.surface.configure(&self.device, &config);
-                        let mut uniforms = self.uniforms.lock().unwrap();
-                        uniforms.update_view_proj(&camera);
-                        self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[*uniforms]));
-                    }
-                }
+                WindowEvent::Resized(new_size) => self.resize(new_size.width, new_size.height),
                 WindowEvent::CloseRequested => *control_flow = ControlFlow::Wait,
                 WindowEvent::KeyboardInput { event, .. } => {
-                    let mut pressed_keys = self.pressed_keys.lock().unwrap();
+                    let pressed_keys = Arc::clone(&self.frame.pressed_keys);
+                    let mut pressed_keys = pressed_keys.lock().unwrap();
                     match event.state {
                         ElementState::Pressed => {
                             match event.physical_key {
@@ -71,6 +107,22 @@ impl AppHandler<'_> {
                                     KeyCode::KeyW | KeyCode::KeyS | KeyCode::KeyA | KeyCode::KeyD => {
                                         pressed_keys.insert(key_code);
                                     }
+                                    KeyCode::KeyM if !event.repeat => {
+                                        let sample_count = next_sample_count(self.render_targets.sample_count, &self.sample_counts);
+                                        self.set_sample_count(sample_count);
+                                    }
+                                    KeyCode::KeyP if !event.repeat => {
+                                        let present_mode = next_present_mode(self.config.lock().unwrap().present_mode, &self.present_modes);
+                                        self.set_present_mode(present_mode);
+                                    }
+                                    KeyCode::KeyF if !event.repeat => {
+                                        let format = next_surface_format(self.config.lock().unwrap().format, &self.surface_formats);
+                                        self.set_surface_format(format);
+                                    }
+                                    KeyCode::KeyO if !event.repeat => {
+                                        self.ssao.settings.enabled = !self.ssao.settings.enabled;
+                                        println!("SSAO: {}", if self.ssao.settings.enabled { "on" } else { "off" });
+                                    }
                                     // Include other keys as needed
                                     _ => {}
                                 },
@@ -99,11 +151,21 @@ impl AppHandler<'_> {
                     let _ = window.set_cursor_position(winit::dpi::PhysicalPosition::new(config.width / 2, config.height / 2));
                 }
                 WindowEvent::RedrawRequested => {
-                    self.log_frame_count += 1;
+                    if self.frame.minimized {
+                        return;
+                    }
+                    let Some(output) = self.acquire_frame() else {
+                        return;
+                    };
+                    self.frame.log_frame_count += 1;
+                    let log_this_frame = self.frame.log_frame_count.is_multiple_of(1000);
+                    self.apply_hot_reload();
 
                     let move_amount = 0.05;
                     let mut camera = self.camera.lock().unwrap();
-                    let pressed_keys = self.pressed_keys.lock().unwrap();
+                    let mut world = self.world.lock().unwrap();
+                    let previous_eye = camera.eye;
+                    let pressed_keys = self.frame.pressed_keys.lock().unwrap();
                     if pressed_keys.contains(&KeyCode::KeyW) {
                         camera.move_forward(move_amount);
                     }
@@ -122,99 +184,73 @@ impl AppHandler<'_> {
                     if pressed_keys.contains(&KeyCode::ShiftLeft) {
                         camera.move_up(-move_amount);
                     }
+                    collide(&mut camera, previous_eye, &world);
 
-                    let current_position = [camera.eye.x, camera.eye.y, camera.eye.z];
-                    let distance_moved = ((current_position[0] - self.last_camera_position[0]).powi(2) +
-                                          (current_position[1] - self.last_camera_position[1]).powi(2) +
-                                          (current_position[2] - self.last_camera_position[2]).powi(2)).sqrt();
-
-                    if distance_moved > self.movement_threshold {
-                        let mut world = self.world.lock().unwrap();
+                    // Reloading as soon as the camera enters another chunk keeps the loaded area at
+                    // least view_distance out in every direction, so fog always hides its edge
+                    let (camera_chunk, _) = camera.chunk_and_offset(world.chunk_size);
+                    if self.frame.last_camera_chunk != Some(camera_chunk) {
                         update_world(&camera, &mut world);
-                        self.last_camera_position = current_position;
-                    }
-
-                    let mut total_vertices: Vec<Vertex> = vec![];
-                    let mut total_indices: Vec<u16> = vec![];
-                    let mut index_offset: u16 = 0;
-
-                    let world = self.world.lock().unwrap();
-                    for chunk in world.chunks.values() {
-                        total_vertices.extend(&chunk.vertices);
-
-                        let indices: Vec<u16> = chunk
-                            .indices
-                            .iter()
-                            .map(|i| *i + index_offset)
-                            .collect();
-                        index_offset += chunk.vertices.len() as u16;
-                        total_indices.extend(indices);
+                        self.frame.last_camera_chunk = Some(camera_chunk);
                     }
 
-                    let total_vertices_bytes = (total_vertices.len() * std::mem::size_of::<Vertex>()) as u64;
-                    let total_indices_bytes = (total_indices.len() * std::mem::size_of::<u16>()) as u64;
+                    // Syncing every frame also picks up chunks remeshed by World::set_block
+                    let visible = visible_chunks(&world, &camera);
+                    let mut chunk_arena = self.chunk_arena.lock().unwrap();
+                    chunk_arena.sync(&self.queue, &world);
+                    chunk_arena.sort_translucent(&self.queue, &camera);
 
-                    if self.log_frame_count % 1000 == 0 {
+                    if log_this_frame {
                         println!("Rendering loop executed.");
-                        println!("Dynamic Vertex Buffer Size: {}, Dynamic Index Buffer Size: {}", total_vertices_bytes, total_indices_bytes);
-                    }
-
-                    let mut dynamic_vertex_buffer_size = self.dynamic_vertex_buffer_size.lock().unwrap();
-                    let mut dynamic_vertex_buffer = self.dynamic_vertex_buffer.lock().unwrap();
-                    if total_vertices_bytes > *dynamic_vertex_buffer_size {
-                        *dynamic_vertex_buffer_size = total_vertices_bytes;
-                        *dynamic_vertex_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("Expanded Vertex Buffer"),
-                            contents: bytemuck::cast_slice(&total_vertices),
-                            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                        });
-                    } else {
-                        self.queue.write_buffer(&*dynamic_vertex_buffer, 0, bytemuck::cast_slice(&total_vertices));
-                    }
-
-                    let mut dynamic_index_buffer_size = self.dynamic_index_buffer_size.lock().unwrap();
-                    let mut dynamic_index_buffer = self.dynamic_index_buffer.lock().unwrap();
-                    if total_indices_bytes > *dynamic_index_buffer_size {
-                        *dynamic_index_buffer_size = total_indices_bytes;
-                        let new_buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                            label: Some("Expanded Index Buffer"),
-                            contents: bytemuck::cast_slice(&total_indices),
-                            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
-                        });
-                        *dynamic_index_buffer = new_buffer;
-                    } else {
-                        self.queue.write_buffer(&*dynamic_index_buffer, 0, bytemuck::cast_slice(&total_indices));
+                        println!("Visible Chunks: {} of {}, Chunks In Arena: {}", visible.len(), world.chunks.len(), chunk_arena.chunk_count());
                     }
 
                     let current_frame_time = std::time::Instant::now();
-                    let mut last_frame_time = self.last_frame_time.lock().unwrap();
-                    let _dt = current_frame_time.duration_since(*last_frame_time).as_secs_f32();
+                    let mut last_frame_time = self.frame.last_frame_time.lock().unwrap();
+                    let dt = current_frame_time.duration_since(*last_frame_time).as_secs_f32();
                     *last_frame_time = current_frame_time;
 
+                    let mut world_clock = self.world_clock.lock().unwrap();
+                    world_clock.advance(dt);
+                    let sky_colors = world_clock.sky_colors();
+                    if log_this_frame {
+                        println!("Time Of Day: {:.1}h", world_clock.time_of_day());
+                    }
+
                     let mut uniforms = self.uniforms.lock().unwrap();
                     uniforms.update_model();
                     uniforms.update_view_proj(&camera);
+                    uniforms.update_camera_origin(&camera, world.chunk_size);
+                    uniforms.update_lighting(&world_clock.sun_light());
+                    uniforms.update_fog_color(sky_colors.horizon);
+                    uniforms.update_shadows(&camera, view_distance(world.chunk_size));
                     self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[*uniforms]));
+                    let sky = self.sky.lock().unwrap();
+                    sky.update(&self.queue, &camera, &sky_colors);
+                    self.ssao.update(&self.queue, &camera);
 
-                    let output = self.surface.get_current_texture().expect("Failed to acquire next swap chain texture");
                     let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
                     let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
                         label: Some("Render Encoder"),
                     });
 
+                    chunk_arena.cull(&self.queue, &mut encoder, &camera, &visible);
+                    self.shadow_maps.lock().unwrap().render(&mut encoder, &self.shadow_bind_group, &chunk_arena);
+                    let screen_size = {
+                        let config = self.config.lock().unwrap();
+                        (config.width, config.height)
+                    };
+                    self.point_lights.lock().unwrap().cull(&self.queue, &mut encoder, &camera, screen_size);
+
+                    let mut instanced_cubes = self.instanced_cubes.lock().unwrap();
+                    instanced_cubes.upload(&self.device, &self.queue, &camera);
+
                     {
                         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                             label: Some("Render Pass"),
-                            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                                view: &view,
-                                resolve_target: None,
-                                ops: wgpu::Operations {
-                                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                                    store: wgpu::StoreOp::Store,
-                                },
-                            })],
+                            color_attachments: &[Some(self.render_targets.color_attachment(wgpu::Color::BLACK))],
                             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                                view: &self.depth_texture_view,
+                                view: self.render_targets.depth_view(),
                                 depth_ops: Some(wgpu::Operations {
                                     load: wgpu::LoadOp::Clear(1.0),
                                     store: wgpu::StoreOp::Store,
@@ -225,16 +261,26 @@ impl AppHandler<'_> {
                             timestamp_writes: None,
                         });
 
+                        sky.draw(&mut render_pass);
+
                         render_pass.set_pipeline(&self.render_pipeline);
                         render_pass.set_bind_group(0, &*self.bind_group, &[]);
+                        chunk_arena.draw(&mut render_pass);
 
-                        render_pass.set_vertex_buffer(0, dynamic_vertex_buffer.slice(..));
-                        render_pass.set_index_buffer(dynamic_index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-                        render_pass.draw_indexed(0..total_indices.len() as u32, 0, 0..1);
+                        render_pass.set_pipeline(&self.instanced_pipeline);
+                        instanced_cubes.draw(&mut render_pass);
+
+                        // Blended last, over everything opaque
+                        render_pass.set_pipeline(&self.translucent_pipeline);
+                        chunk_arena.draw_translucent(&mut render_pass, &camera, &visible);
                     }
 
+                    let scene = self.ssao.render(&mut encoder, &self.render_targets);
+                    self.post_chain.render(&self.device, &mut encoder, scene, &view);
+
                     self.queue.submit(Some(encoder.finish()));
                     output.present();
+                    self.frame_pacer.wait();
                 }
                 _ => {}
             },
@@ -243,6 +289,176 @@ impl AppHandler<'_> {
     }
 }
 
+// Moves the eye from where it was one axis at a time, keeping only the axes that don't end inside a
+// solid block, so the camera slides along walls instead of stopping dead
+fn collide(camera: &mut Camera, previous_eye: Point3<f32>, world: &World) {
+    let look = camera.target - camera.eye;
+    let moved_eye = camera.eye;
+    camera.eye = previous_eye;
+    for axis in 0..3 {
+        let mut eye = camera.eye;
+        eye[axis] = moved_eye[axis];
+        if !world.is_solid_at([eye.x.floor() as i32, eye.y.floor() as i32, eye.z.floor() as i32]) {
+            camera.eye = eye;
+        }
+    }
+    camera.target = camera.eye + look;
+}
+
+// Every pipeline drawn into the main pass, rebuilt together since they must match its attachments
+struct ScenePipelines {
+    render: wgpu::RenderPipeline,
+    instanced: wgpu::RenderPipeline,
+    translucent: wgpu::RenderPipeline,
+}
+
+impl ScenePipelines {
+    fn new(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule, sample_count: u32) -> Self {
+        Self {
+            render: create_render_pipeline(device, layout, shader, HDR_FORMAT, sample_count),
+            instanced: create_instanced_pipeline(device, layout, shader, HDR_FORMAT, sample_count),
+            translucent: create_translucent_pipeline(device, layout, shader, HDR_FORMAT, sample_count),
+        }
+    }
+}
+
+impl AppHandler<'_> {
+    // Reconfigures the surface and recreates everything sized to it. A minimised window reports a
+    // zero size, which can't be configured, so rendering just stops until it is restored.
+    fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            self.frame.minimized = true;
+            return;
+        }
+        self.frame.minimized = false;
+
+        let mut config = self.config.lock().unwrap();
+        config.width = width;
+        config.height = height;
+        self.surface.configure(&self.device, &config);
+        self.render_targets = RenderTargets::new(&self.device, width, height, self.render_targets.sample_count);
+        self.post_chain.resize(&self.device, width, height);
+        self.ssao.resize(&self.device, &self.render_targets, width, height);
+
+        let mut camera = self.camera.lock().unwrap();
+        camera.aspect = width as f32 / height as f32;
+        let mut uniforms = self.uniforms.lock().unwrap();
+        uniforms.update_view_proj(&camera);
+        self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[*uniforms]));
+    }
+
+    // The next swapchain texture, or None when this frame has to be skipped. An outdated or lost
+    // swapchain is recreated at the window's current size for the next frame.
+    fn acquire_frame(&mut self) -> Option<wgpu::SurfaceTexture> {
+        match self.surface.get_current_texture() {
+            Ok(output) => Some(output),
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                let size = self.window.inner_size();
+                self.resize(size.width, size.height);
+                self.window.request_redraw();
+                None
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                println!("Timed out acquiring a swapchain texture, skipping the frame");
+                None
+            }
+            Err(wgpu::SurfaceError::OutOfMemory) => panic!("Out of memory acquiring a swapchain texture"),
+        }
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) {
+        let mut config = self.config.lock().unwrap();
+        config.present_mode = present_mode;
+        if !self.frame.minimized {
+            self.surface.configure(&self.device, &config);
+        }
+        println!("Present mode: {:?}", present_mode);
+    }
+
+    // Only the post-processing chain's last copy writes to the surface, so it is the only
+    // pipeline rebuilt for the new format
+    pub fn set_surface_format(&mut self, format: wgpu::TextureFormat) {
+        let mut config = self.config.lock().unwrap();
+        config.format = format;
+        if !self.frame.minimized {
+            self.surface.configure(&self.device, &config);
+        }
+        self.post_chain.set_output_format(&self.device, format);
+        println!("Surface format: {:?}", format);
+    }
+
+    // Switches the MSAA level, clamped to what the adapter supports, recreating the
+    // attachments and every pipeline that draws into them
+    pub fn set_sample_count(&mut self, requested: u32) {
+        let sample_count = clamp_sample_count(requested, &self.sample_counts);
+        if sample_count == self.render_targets.sample_count {
+            return;
+        }
+
+        let (width, height) = {
+            let config = self.config.lock().unwrap();
+            (config.width, config.height)
+        };
+        self.render_targets = RenderTargets::new(&self.device, width, height, sample_count);
+        let pipelines = ScenePipelines::new(&self.device, &self.pipeline_layout, &self.shader, sample_count);
+        self.sky.lock().unwrap().set_sample_count(&self.device, HDR_FORMAT, sample_count);
+        self.ssao.set_sample_count(&self.device, &self.render_targets);
+        self.render_pipeline = Arc::new(pipelines.render);
+        self.instanced_pipeline = Arc::new(pipelines.instanced);
+        self.translucent_pipeline = Arc::new(pipelines.translucent);
+        println!("MSAA: {}x", sample_count);
+    }
+
+    // Swaps in rebuilt pipelines and textures when watched files change, keeping the old ones on failure
+    fn apply_hot_reload(&mut self) {
+        let Some(hot_reload) = self.hot_reload.as_mut() else {
+            return;
+        };
+        let changes = hot_reload.poll();
+
+        if changes.shader {
+            let mut shadow_maps = self.shadow_maps.lock().unwrap();
+            let sample_count = self.render_targets.sample_count;
+            let result = hot_reload.reload_shader(&self.device, |shader| (
+                ScenePipelines::new(&self.device, &self.pipeline_layout, shader, sample_count),
+                create_shadow_pipeline(&self.device, &shadow_maps.pipeline_layout, shader),
+            ));
+            match result {
+                Ok((shader, (scene_pipelines, shadow_pipeline))) => {
+                    self.shader = Arc::new(shader);
+                    self.render_pipeline = Arc::new(scene_pipelines.render);
+                    self.instanced_pipeline = Arc::new(scene_pipelines.instanced);
+                    self.translucent_pipeline = Arc::new(scene_pipelines.translucent);
+                    shadow_maps.pipeline = shadow_pipeline;
+                    println!("Reloaded shader");
+                }
+                Err(error) => println!("Shader reload failed, keeping the previous pipeline: {}", error),
+            }
+        }
+
+        if changes.textures {
+            match hot_reload.reload_textures(&self.device, &self.queue) {
+                Ok(texture) => {
+                    self.bind_group = Arc::new(create_bind_group(
+                        &self.device,
+                        &self.bind_group_layout,
+                        &self.uniform_buffer,
+                        &texture,
+                        &self.chunk_arena.lock().unwrap(),
+                        &self.block_face_layer_buffer,
+                        LightBindings {
+                            shadow_maps: &self.shadow_maps.lock().unwrap(),
+                            point_lights: &self.point_lights.lock().unwrap(),
+                        },
+                    ));
+                    println!("Reloaded block textures");
+                }
+                Err(error) => println!("Texture reload failed, keeping the previous textures: {}", error),
+            }
+        }
+    }
+}
+
 impl ApplicationHandler<()> for AppHandler<'_> {
     fn resumed(&mut self, _event_loop: &ActiveEventLoop) {
         // Placeholder for resumed event handling
@@ -261,61 +477,6 @@ impl ApplicationHandler<()> for AppHandler<'_> {
     }
 }
 
-pub fn handle_event_loop(
-    event_loop: EventLoop<()>, 
-    window: Arc<winit::window::Window>, 
-    device: Arc<wgpu::Device>, 
-    queue: Arc<wgpu::Queue>, 
-    surface: Arc<wgpu::Surface>, 
-    config: Arc<Mutex<wgpu::SurfaceConfiguration>>, 
-    bind_group: Arc<wgpu::BindGroup>, 
-    render_pipeline: Arc<wgpu::RenderPipeline>, 
-    dynamic_vertex_buffer: Arc<Mutex<wgpu::Buffer>>, 
-    dynamic_index_buffer: Arc<Mutex<wgpu::Buffer>>, 
-    uniform_buffer: Arc<wgpu::Buffer>, 
-    depth_texture_view: Arc<wgpu::TextureView>, 
-    camera: Arc<Mutex<Camera>>, 
-    world: Arc<Mutex<World>>, 
-    uniforms: Arc<Mutex<Uniforms>>,
-) {
-    let last_frame_time = Arc::new(Mutex::new(std::time::Instant::now()));
-    let pressed_keys = Arc::new(Mutex::new(HashSet::new()));
-
-    let dynamic_vertex_buffer_size = Arc::new(Mutex::new(1024 * 1024 * std::mem::size_of::<Vertex>() as u64));
-    let dynamic_index_buffer_size = Arc::new(Mutex::new(1024 * 1024 * std::mem::size_of::<u16>() as u64));
-
-    let _texture = get_texture(&device, &queue, [
-        "src/images/pos_x.png",
-        "src/images/neg_x.png",
-        "src/images/pos_y.png",
-        "src/images/neg_y.png",
-        "src/images/pos_z.png",
-        "src/images/neg_z.png",
-    ]);
-
-    let mut app_handler = AppHandler {
-        surface,
-        device,
-        queue,
-        config,
-        bind_group,
-        render_pipeline,
-        dynamic_vertex_buffer,
-        dynamic_index_buffer,
-        uniform_buffer,
-        depth_texture_view,
-        camera,
-        world,
-        uniforms,
-        last_frame_time,
-        pressed_keys,
-        log_frame_count: 0,
-        last_camera_position: [0.0, 0.0, 0.0],
-        movement_threshold: 10.0,
-        dynamic_vertex_buffer_size, // Added
-        dynamic_index_buffer_size,   // Added
-        window,
-    };
-
+pub fn handle_event_loop(event_loop: EventLoop<()>, mut app_handler: AppHandler) {
     let _ = event_loop.run_app(&mut app_handler);
 }
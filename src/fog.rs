@@ -0,0 +1,42 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum FogMode {
+    Linear,
+    Exponential,
+}
+
+// Read from the [fog] table of sky.toml, the fog end itself always follows the view distance
+#[derive(Deserialize, Copy, Clone)]
+#[serde(default)]
+pub struct FogSettings {
+    pub mode: FogMode,
+    pub start: f32, // Fraction of the fog end where linear fog begins
+    pub color: [f32; 3], // Should match the sky near the horizon
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::Linear,
+            start: 0.5,
+            color: [0.6, 0.75, 0.9],
+        }
+    }
+}
+
+// Exponential fog reaches this density at the fog end, the shader hides everything past it completely
+const EXPONENTIAL_FOG_AT_END: f32 = 0.99;
+
+impl FogSettings {
+    // Packed as (start, end, density, mode) for the shader
+    pub fn params(&self, fog_end: f32) -> [f32; 4] {
+        let density = -(1.0 - EXPONENTIAL_FOG_AT_END).ln() / fog_end;
+        let mode = match self.mode {
+            FogMode::Linear => 0.0,
+            FogMode::Exponential => 1.0,
+        };
+        [self.start * fog_end, fog_end, density, mode]
+    }
+}
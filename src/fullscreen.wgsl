@@ -0,0 +1,36 @@
+// Shared by every post-processing pass, effect shaders are appended to this source.
+// Binding 3 is left to the effect for an extra texture (the bloom blur, a color-grading LUT).
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+struct PostParams {
+    values: vec4<f32>, // Meaning depends on the effect
+}
+
+@group(0) @binding(0)
+var input_texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var input_sampler: sampler;
+
+@group(0) @binding(2)
+var<uniform> params: PostParams;
+
+// One triangle covering the whole screen, no vertex buffer needed
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+// Copies the end of the chain into the window
+@fragment
+fn fs_copy(input: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(textureSample(input_texture, input_sampler, input.uv).rgb, 1.0);
+}
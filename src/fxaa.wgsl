@@ -0,0 +1,48 @@
+// FXAA in the style of the original console version: finds the local edge direction from luma
+// and blends along it. Runs after gamma, where luma matches what the eye sees.
+
+const FXAA_REDUCE_MIN: f32 = 1.0 / 128.0;
+const FXAA_REDUCE_MUL: f32 = 1.0 / 8.0;
+const FXAA_SPAN_MAX: f32 = 8.0;
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+fn sample_at(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(input_texture, input_sampler, uv, 0.0).rgb;
+}
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(input_texture));
+    let uv = input.uv;
+
+    let rgb_m = sample_at(uv);
+    let luma_nw = luma(sample_at(uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_at(uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_at(uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_at(uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(rgb_m);
+
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    var dir = vec2<f32>(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    let rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+    dir = clamp(dir * rcp_dir_min, vec2<f32>(-FXAA_SPAN_MAX), vec2<f32>(FXAA_SPAN_MAX)) * texel;
+
+    let rgb_a = 0.5 * (sample_at(uv + dir * (1.0 / 3.0 - 0.5)) + sample_at(uv + dir * (2.0 / 3.0 - 0.5)));
+    let rgb_b = rgb_a * 0.5 + 0.25 * (sample_at(uv - dir * 0.5) + sample_at(uv + dir * 0.5));
+
+    // The wider blend is only kept when it didn't pull in colors from across the edge
+    let luma_b = luma(rgb_b);
+    if (luma_b < luma_min || luma_b > luma_max) {
+        return vec4<f32>(rgb_a, 1.0);
+    }
+    return vec4<f32>(rgb_b, 1.0);
+}
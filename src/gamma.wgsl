@@ -0,0 +1,7 @@
+// params.values.x: gamma
+
+@fragment
+fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, input.uv).rgb;
+    return vec4<f32>(pow(max(color, vec3<f32>(0.0)), vec3<f32>(1.0 / params.values.x)), 1.0);
+}
@@ -0,0 +1,183 @@
+// Golden-image regression tests. Fixed scenes are rendered on the fallback adapter and compared
+// against the references in tests/golden. Set MUTETRA_UPDATE_GOLDEN=1 to rewrite the references
+// after an intended change; on a mismatch the render and a diff image are written to target/golden.
+
+use std::sync::Arc;
+use crate::block::BlockRegistry;
+use crate::camera::Camera;
+use crate::headless::HeadlessRenderer;
+use crate::point_light::PointLight;
+use crate::world::World;
+use crate::world_update::update_world;
+
+const UPDATE_ENV: &str = "MUTETRA_UPDATE_GOLDEN";
+const REFERENCE_DIR: &str = "tests/golden";
+const OUTPUT_DIR: &str = "target/golden";
+const SIZE: (u32, u32) = (320, 180);
+
+// A pixel differs when its CIE76 color difference is above this (around 2.3 is just noticeable),
+// and a scene fails when more than MAX_DIFFERING of its pixels differ
+const MAX_DELTA_E: f32 = 6.0;
+const MAX_DIFFERING: f32 = 0.002;
+
+struct Scene {
+    name: &'static str,
+    seed: u64,
+    eye: [f32; 3],
+    yaw: f32, // Degrees
+    pitch: f32,
+    time_of_day: f32,
+    point_light: Option<PointLight>,
+}
+
+fn scenes() -> Vec<Scene> {
+    vec![
+        // Bare ground in the morning, the sky and fog
+        Scene { name: "flat_morning", seed: 0, eye: [0.0, 4.0, 6.0], yaw: -90.0, pitch: -30.0, time_of_day: 9.0, point_light: None },
+        // Scattered columns up close at noon, for face textures, voxel AO and shadows. Textures
+        // swimming across faces (see docs/tearing.txt) shows up here first.
+        Scene { name: "columns_noon", seed: 7, eye: [2.5, 3.5, 7.0], yaw: -100.0, pitch: -20.0, time_of_day: 12.0, point_light: None },
+        // The same kind of scene at night, lit by a point light
+        Scene {
+            name: "columns_night_light",
+            seed: 21,
+            eye: [0.5, 4.0, 6.5],
+            yaw: -90.0,
+            pitch: -30.0,
+            time_of_day: 0.0,
+            point_light: Some(PointLight {
+                position: (0.5, 2.5, 1.5).into(),
+                color: [1.0, 0.7, 0.4],
+                radius: 8.0,
+                intensity: 6.0,
+            }),
+        },
+    ]
+}
+
+// Columns of ground blocks at positions picked by a xorshift generator, so every seed always
+// builds the same scene
+fn scatter_columns(world: &mut World, seed: u64) {
+    if seed == 0 {
+        return;
+    }
+    let ground = world.registry.id("ground").expect("Block registry has no ground block");
+    let mut state = seed;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for _ in 0..12 {
+        let x = (next() % 9) as i32 - 4;
+        let z = (next() % 9) as i32 - 4;
+        let height = (next() % 3) as i32 + 1;
+        for y in 1..=height {
+            world.set_block([x, y, z], ground);
+        }
+    }
+}
+
+fn render_scene(renderer: &mut HeadlessRenderer, registry: &Arc<BlockRegistry>, scene: &Scene) -> image::RgbaImage {
+    let mut world = World::new(8, registry.clone());
+    let mut camera = Camera::new();
+    camera.eye = scene.eye.into();
+    camera.yaw = scene.yaw.to_radians();
+    camera.pitch = scene.pitch.to_radians();
+    camera.aspect = SIZE.0 as f32 / SIZE.1 as f32;
+    camera.update_camera_vectors();
+    update_world(&camera, &mut world);
+    scatter_columns(&mut world, scene.seed);
+
+    let light = scene.point_light.and_then(|light| renderer.point_lights_mut().spawn(light));
+    let image = renderer.render(&world, &camera, scene.time_of_day);
+    if let Some(light) = light {
+        renderer.point_lights_mut().remove(light);
+    }
+    image
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+// CIE L*a*b* of an sRGB pixel, under D65
+fn to_lab(pixel: &image::Rgba<u8>) -> [f32; 3] {
+    let [r, g, b] = [srgb_to_linear(pixel[0]), srgb_to_linear(pixel[1]), srgb_to_linear(pixel[2])];
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.9505;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.089;
+    let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn delta_e(a: &image::Rgba<u8>, b: &image::Rgba<u8>) -> f32 {
+    let (a, b) = (to_lab(a), to_lab(b));
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+// The fraction of differing pixels, and an image of the reference dimmed to gray with every
+// differing pixel in red
+fn compare(actual: &image::RgbaImage, reference: &image::RgbaImage) -> (f32, image::RgbaImage) {
+    let mut diff = image::RgbaImage::new(reference.width(), reference.height());
+    let mut differing = 0;
+    for (x, y, expected) in reference.enumerate_pixels() {
+        let pixel = if delta_e(actual.get_pixel(x, y), expected) > MAX_DELTA_E {
+            differing += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            let gray = ((expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 9) as u8;
+            image::Rgba([gray, gray, gray, 255])
+        };
+        diff.put_pixel(x, y, pixel);
+    }
+    (differing as f32 / (reference.width() * reference.height()) as f32, diff)
+}
+
+#[test]
+fn golden_images() {
+    let registry = Arc::new(BlockRegistry::load("src/blocks.toml").expect("Failed to load block registry"));
+    // Without MSAA, which the GL fallback adapter can't resolve next to a sampled depth texture
+    let mut renderer = pollster::block_on(HeadlessRenderer::new(&registry, 8, SIZE.0, SIZE.1, 1))
+        .expect("Golden-image tests need a fallback adapter (e.g. Mesa llvmpipe or lavapipe)");
+    let update = std::env::var(UPDATE_ENV).is_ok_and(|value| value == "1");
+
+    let mut failures = Vec::new();
+    for scene in scenes() {
+        let actual = render_scene(&mut renderer, &registry, &scene);
+        let reference_path = format!("{}/{}.png", REFERENCE_DIR, scene.name);
+        if update {
+            std::fs::create_dir_all(REFERENCE_DIR).expect("Failed to create the reference directory");
+            actual.save(&reference_path).expect("Failed to write reference image");
+            println!("Updated {}", reference_path);
+            continue;
+        }
+
+        let reference = match image::open(&reference_path) {
+            Ok(reference) => reference.to_rgba8(),
+            Err(e) => {
+                failures.push(format!("{}: no reference image at {} ({}), run with {}=1 to create it", scene.name, reference_path, e, UPDATE_ENV));
+                continue;
+            }
+        };
+        if reference.dimensions() != actual.dimensions() {
+            failures.push(format!("{}: reference is {:?}, render is {:?}", scene.name, reference.dimensions(), actual.dimensions()));
+            continue;
+        }
+
+        let (differing, diff) = compare(&actual, &reference);
+        if differing > MAX_DIFFERING {
+            std::fs::create_dir_all(OUTPUT_DIR).expect("Failed to create the golden output directory");
+            let actual_path = format!("{}/{}.actual.png", OUTPUT_DIR, scene.name);
+            let diff_path = format!("{}/{}.diff.png", OUTPUT_DIR, scene.name);
+            actual.save(&actual_path).expect("Failed to write render");
+            diff.save(&diff_path).expect("Failed to write diff image");
+            failures.push(format!("{}: {:.2}% of pixels differ (at most {:.2}% allowed), see {} and {}", scene.name, differing * 100.0, MAX_DIFFERING * 100.0, actual_path, diff_path));
+        }
+    }
+
+    assert!(failures.is_empty(), "Golden images differ:\n{}", failures.join("\n"));
+}
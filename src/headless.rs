@@ -0,0 +1,285 @@
+use std::sync::{Arc, mpsc};
+use wgpu::util::DeviceExt;
+use crate::block::BlockRegistry;
+use crate::camera::Camera;
+use crate::day_night::WorldClock;
+use crate::indirect::ChunkArena;
+use crate::occlusion::visible_chunks;
+use crate::pipeline::{LightBindings, create_bind_group, create_bind_group_layout, create_render_pipeline, create_translucent_pipeline};
+use crate::point_light::PointLights;
+use crate::post::{PostChain, PostConfig};
+use crate::render_targets::{RenderTargets, HDR_FORMAT, MSAA_FEATURES, clamp_sample_count, supported_sample_counts};
+use crate::shadow::ShadowMaps;
+use crate::sky::{Sky, SkyConfig};
+use crate::ssao::Ssao;
+use crate::texture::Texture;
+use crate::uniforms::Uniforms;
+use crate::world::World;
+use crate::world_update::{update_world, view_distance};
+
+// Plain 8-bit output, the post-processing chain's gamma effect has already encoded it
+const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+const THUMBNAIL_SIZE: (u32, u32) = (640, 360);
+
+// Renders frames of a world into an offscreen texture and reads them back, without a window.
+// It runs on the fallback (software) adapter so it works on build servers without a GPU, and
+// draws the same passes as the windowed renderer except the instanced demo cubes.
+pub struct HeadlessRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    width: u32,
+    height: u32,
+    uniforms: Uniforms,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    translucent_pipeline: wgpu::RenderPipeline,
+    chunk_arena: ChunkArena,
+    shadow_maps: ShadowMaps,
+    shadow_bind_group: wgpu::BindGroup,
+    point_lights: PointLights,
+    sky: Sky,
+    fog_color: [f32; 3],
+    render_targets: RenderTargets,
+    ssao: Ssao,
+    post_chain: PostChain,
+    output_texture: wgpu::Texture,
+}
+
+impl HeadlessRenderer {
+    // `sample_count` is clamped to what the adapter supports
+    pub async fn new(registry: &BlockRegistry, chunk_size: usize, width: u32, height: u32, sample_count: u32) -> Result<Self, String> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        }).await.ok_or("No fallback adapter available for headless rendering")?;
+
+        let required_features = adapter.features() & (wgpu::Features::MULTI_DRAW_INDIRECT | MSAA_FEATURES);
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                required_features,
+                ..Default::default()
+            }, None)
+            .await
+            .map_err(|e| format!("Failed to create headless device: {}", e))?;
+
+        let sample_count = clamp_sample_count(sample_count, &supported_sample_counts(&adapter, &device));
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
+        });
+
+        let texture_paths: Vec<&str> = registry.texture_paths.iter().map(|path| path.as_str()).collect();
+        let texture = Texture::try_from_layers(&device, &queue, &texture_paths, &registry.texture_cutout)?;
+        let block_face_layer_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Block Face Layer Buffer"),
+            contents: bytemuck::cast_slice(&registry.face_layer_table()),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+        let sky_config = SkyConfig::load("src/sky.toml")?;
+        let mut uniforms = Uniforms::new();
+        uniforms.update_fog(&sky_config.fog, view_distance(chunk_size));
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Uniform Buffer"),
+            contents: bytemuck::cast_slice(&[uniforms]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let chunk_arena = ChunkArena::new(&device, chunk_size);
+        let bind_group_layout = create_bind_group_layout(&device);
+        let shadow_maps = ShadowMaps::new(&device, &shader);
+        let shadow_bind_group = shadow_maps.create_bind_group(&device, &uniform_buffer, &chunk_arena);
+        let point_lights = PointLights::new(&device);
+        let lights = LightBindings { shadow_maps: &shadow_maps, point_lights: &point_lights };
+        let bind_group = create_bind_group(&device, &bind_group_layout, &uniform_buffer, &texture, &chunk_arena, &block_face_layer_buffer, lights);
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Render Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = create_render_pipeline(&device, &pipeline_layout, &shader, HDR_FORMAT, sample_count);
+        let translucent_pipeline = create_translucent_pipeline(&device, &pipeline_layout, &shader, HDR_FORMAT, sample_count);
+        let sky = Sky::new(&device, &queue, &sky_config, HDR_FORMAT, sample_count);
+
+        let render_targets = RenderTargets::new(&device, width, height, sample_count);
+        let post_config = PostConfig::load("src/post.toml")?;
+        let ssao = Ssao::new(&device, post_config.ssao, &render_targets, width, height);
+        let post_chain = PostChain::from_config(&device, &queue, &post_config, OUTPUT_FORMAT, width, height)?;
+
+        let output_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless Output Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: OUTPUT_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        Ok(Self {
+            device,
+            queue,
+            width,
+            height,
+            uniforms,
+            uniform_buffer,
+            bind_group,
+            render_pipeline,
+            translucent_pipeline,
+            chunk_arena,
+            shadow_maps,
+            shadow_bind_group,
+            point_lights,
+            sky,
+            fog_color: sky_config.fog.color,
+            render_targets,
+            ssao,
+            post_chain,
+            output_texture,
+        })
+    }
+
+    // Lights spawned here show up in the following renders
+    pub fn point_lights_mut(&mut self) -> &mut PointLights {
+        &mut self.point_lights
+    }
+
+    // Renders the chunks of `world` already loaded around `camera` at the given time of day (in
+    // hours) and blocks until the frame is read back. The camera's aspect should match the size.
+    pub fn render(&mut self, world: &World, camera: &Camera, time_of_day: f32) -> image::RgbaImage {
+        let world_clock = WorldClock::new(time_of_day, self.fog_color);
+        let sky_colors = world_clock.sky_colors();
+        self.uniforms.update_model();
+        self.uniforms.update_view_proj(camera);
+        self.uniforms.update_camera_origin(camera, world.chunk_size);
+        self.uniforms.update_lighting(&world_clock.sun_light());
+        self.uniforms.update_fog_color(sky_colors.horizon);
+        self.uniforms.update_shadows(camera, view_distance(world.chunk_size));
+        self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[self.uniforms]));
+        self.sky.update(&self.queue, camera, &sky_colors);
+        self.ssao.update(&self.queue, camera);
+
+        let visible = visible_chunks(world, camera);
+        self.chunk_arena.sync(&self.queue, world);
+        self.chunk_arena.sort_translucent(&self.queue, camera);
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless Encoder"),
+        });
+
+        self.chunk_arena.cull(&self.queue, &mut encoder, camera, &visible);
+        self.shadow_maps.render(&mut encoder, &self.shadow_bind_group, &self.chunk_arena);
+        self.point_lights.cull(&self.queue, &mut encoder, camera, (self.width, self.height));
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Headless Render Pass"),
+                color_attachments: &[Some(self.render_targets.color_attachment(wgpu::Color::BLACK))],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: self.render_targets.depth_view(),
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            self.sky.draw(&mut render_pass);
+
+            render_pass.set_pipeline(&self.render_pipeline);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            self.chunk_arena.draw(&mut render_pass);
+
+            render_pass.set_pipeline(&self.translucent_pipeline);
+            self.chunk_arena.draw_translucent(&mut render_pass, camera, &visible);
+        }
+
+        let output_view = self.output_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let scene = self.ssao.render(&mut encoder, &self.render_targets);
+        self.post_chain.render(&self.device, &mut encoder, scene, &output_view);
+
+        // Buffer rows have to be 256-byte aligned, the padding is dropped when reading back
+        let bytes_per_row = (4 * self.width).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Headless Readback Buffer"),
+            size: (bytes_per_row * self.height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &self.output_texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            self.output_texture.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv()
+            .expect("Readback callback was dropped")
+            .expect("Failed to map the headless readback buffer");
+
+        let mut pixels = Vec::with_capacity((4 * self.width * self.height) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..(4 * self.width) as usize]);
+            }
+        }
+        readback_buffer.unmap();
+
+        image::RgbaImage::from_raw(self.width, self.height, pixels).expect("Readback size mismatch")
+    }
+}
+
+// Renders one frame of `world` from `camera` and writes it to `path` as a PNG
+pub fn render_to_png(renderer: &mut HeadlessRenderer, world: &World, camera: &Camera, time_of_day: f32, path: &str) -> Result<(), String> {
+    renderer.render(world, camera, time_of_day)
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|e| format!("Failed to write {}: {}", path, e))
+}
+
+// What `cargo run -- --render <path>` writes: a fresh world seen from above the spawn point in
+// the morning, without MSAA (the GL fallback adapter can't resolve it next to a sampled depth texture)
+pub async fn render_thumbnail(path: &str) -> Result<(), String> {
+    let registry = Arc::new(BlockRegistry::load("src/blocks.toml")?);
+    let mut world = World::new(8, registry.clone());
+    let mut camera = Camera::new();
+    camera.eye = (0.0, 4.0, 6.0).into();
+    camera.pitch = -30.0f32.to_radians();
+    camera.update_camera_vectors();
+    camera.aspect = THUMBNAIL_SIZE.0 as f32 / THUMBNAIL_SIZE.1 as f32;
+    update_world(&camera, &mut world);
+
+    let mut renderer = HeadlessRenderer::new(&registry, world.chunk_size, THUMBNAIL_SIZE.0, THUMBNAIL_SIZE.1, 1).await?;
+    render_to_png(&mut renderer, &world, &camera, 9.0, path)
+}
@@ -0,0 +1,104 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use crate::block::BlockRegistry;
+use crate::texture::Texture;
+
+// Set this environment variable (to anything) to watch shaders and textures while the engine runs
+pub const HOT_RELOAD_ENV: &str = "MUTETRA_HOT_RELOAD";
+pub const SHADER_PATH: &str = "src/shader.wgsl";
+
+// Checking modification times is cheap, but there is no need to do it every frame
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+struct WatchedFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl WatchedFile {
+    fn new(path: &str) -> Self {
+        let path = PathBuf::from(path);
+        let modified = modified_time(&path);
+        Self { path, modified }
+    }
+
+    // True once per change, the first time a new modification time is seen
+    fn changed(&mut self) -> bool {
+        let modified = modified_time(&self.path);
+        if modified != self.modified {
+            self.modified = modified;
+            return true;
+        }
+        false
+    }
+}
+
+fn modified_time(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[derive(Default)]
+pub struct Changes {
+    pub shader: bool,
+    pub textures: bool,
+}
+
+pub struct HotReload {
+    shader: WatchedFile,
+    textures: Vec<WatchedFile>,
+    texture_cutout: Vec<bool>,
+    last_poll: Instant,
+}
+
+impl HotReload {
+    // Dev mode is opt-in, release builds never touch the source tree
+    pub fn from_env(registry: &BlockRegistry) -> Option<Self> {
+        std::env::var_os(HOT_RELOAD_ENV)?;
+        println!("Hot reload enabled, watching {} and {} textures", SHADER_PATH, registry.texture_paths.len());
+        Some(Self {
+            shader: WatchedFile::new(SHADER_PATH),
+            textures: registry.texture_paths.iter().map(|path| WatchedFile::new(path)).collect(),
+            texture_cutout: registry.texture_cutout.clone(),
+            last_poll: Instant::now(),
+        })
+    }
+
+    pub fn poll(&mut self) -> Changes {
+        let mut changes = Changes::default();
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return changes;
+        }
+        self.last_poll = Instant::now();
+
+        changes.shader = self.shader.changed();
+        // Every file is checked so one edit doesn't trigger a second reload on the next poll
+        for texture in self.textures.iter_mut() {
+            changes.textures |= texture.changed();
+        }
+        changes
+    }
+
+    // Compiles the WGSL on disk and hands it to `build`, which creates every pipeline that uses it.
+    // Validation errors are caught in an error scope so the caller can keep the old pipelines.
+    // The module is returned too, for pipelines rebuilt later on (e.g. when the MSAA level changes).
+    pub fn reload_shader<T>(&self, device: &wgpu::Device, build: impl FnOnce(&wgpu::ShaderModule) -> T) -> Result<(wgpu::ShaderModule, T), String> {
+        let source = std::fs::read_to_string(&self.shader.path)
+            .map_err(|e| format!("Failed to read {}: {}", self.shader.path.display(), e))?;
+
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipelines = build(&shader);
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(error) => Err(error.to_string()),
+            None => Ok((shader, pipelines)),
+        }
+    }
+
+    pub fn reload_textures(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Texture, String> {
+        let paths: Vec<&str> = self.textures.iter().filter_map(|texture| texture.path.to_str()).collect();
+        Texture::try_from_layers(device, queue, &paths, &self.texture_cutout)
+    }
+}
@@ -0,0 +1,550 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use bytemuck::{Pod, Zeroable};
+use crate::camera::Camera;
+use crate::vertex::PackedVertex;
+use crate::world::World;
+
+const MAX_CHUNKS: u32 = 1024; // Must fit the 12 slot bits of PackedVertex
+const ARENA_VERTICES: u32 = 1024 * 1024;
+const ARENA_INDICES: u32 = 1024 * 1024;
+const CULL_WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct CullUniforms {
+    planes: [[f32; 4]; 6],
+    camera_chunk: [i32; 4],
+    camera_offset: [f32; 4],
+    chunk_count: u32,
+    _padding: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ChunkDraw {
+    chunk_pos: [i32; 4],
+    aabb_min: [f32; 4],
+    aabb_max: [f32; 4],
+    index_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    _padding: u32,
+}
+
+// First-fit allocator handing out element ranges inside one of the arena buffers
+struct RangeAllocator {
+    free: Vec<Range<u32>>,
+}
+
+impl RangeAllocator {
+    fn new(size: u32) -> Self {
+        Self { free: std::iter::once(0..size).collect() } // One free range covering everything
+    }
+
+    fn alloc(&mut self, len: u32) -> Option<Range<u32>> {
+        if len == 0 {
+            return Some(0..0);
+        }
+        let position = self.free.iter().position(|range| range.end - range.start >= len)?;
+        let start = self.free[position].start;
+        self.free[position].start += len;
+        if self.free[position].start == self.free[position].end {
+            self.free.remove(position);
+        }
+        Some(start..start + len)
+    }
+
+    fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        self.free.push(range);
+        self.free.sort_by_key(|range| range.start);
+
+        let mut merged: Vec<Range<u32>> = Vec::with_capacity(self.free.len());
+        for range in self.free.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => merged.push(range),
+            }
+        }
+        self.free = merged;
+    }
+}
+
+// Translucent faces share the chunk's slot but keep their own ranges, the index range is
+// rewritten in back-to-front order whenever the camera moves into another block
+struct TranslucentMesh {
+    vertices: Range<u32>,
+    indices: Range<u32>,
+    faces: Vec<[u16; 6]>,
+    centers: Vec<[f32; 3]>, // Local to the chunk, one per face
+    sorted_from: Option<[i32; 3]>,
+}
+
+impl TranslucentMesh {
+    fn new(vertices: Range<u32>, indices: Range<u32>, mesh_vertices: &[PackedVertex], mesh_indices: &[u16]) -> Self {
+        let faces: Vec<[u16; 6]> = mesh_indices.chunks_exact(6)
+            .map(|face| face.try_into().unwrap())
+            .collect();
+
+        // Both triangles share a diagonal, so the six corners still average to the middle of the quad
+        let centers = faces.iter().map(|face| {
+            let mut center = [0.0; 3];
+            for &index in face {
+                let local = mesh_vertices[index as usize].local_position();
+                for axis in 0..3 {
+                    center[axis] += local[axis] as f32 / 6.0;
+                }
+            }
+            center
+        }).collect();
+
+        Self { vertices, indices, faces, centers, sorted_from: None }
+    }
+}
+
+struct ArenaSlot {
+    slot: u32,
+    vertices: Range<u32>,
+    indices: Range<u32>,
+    index_count: u32, // Without the padding index
+    translucent: Option<TranslucentMesh>,
+    revision: u32,
+}
+
+// Every loaded chunk mesh lives in one shared vertex/index buffer, drawn with GPU-written indirect args
+pub struct ChunkArena {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    chunk_buffer: wgpu::Buffer,
+    visibility_buffer: wgpu::Buffer,
+    cull_uniform_buffer: wgpu::Buffer,
+    draw_buffer: wgpu::Buffer,
+    cull_pipeline: wgpu::ComputePipeline,
+    cull_bind_group: wgpu::BindGroup,
+    vertex_ranges: RangeAllocator,
+    index_ranges: RangeAllocator,
+    slots: HashMap<(i32, i32), ArenaSlot>,
+    free_slots: Vec<u32>,
+    slot_count: u32,
+    chunk_size: usize,
+    multi_draw: bool,
+}
+
+impl ChunkArena {
+    pub fn new(device: &wgpu::Device, chunk_size: usize) -> Self {
+        let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Arena Vertex Buffer"),
+            size: ARENA_VERTICES as u64 * std::mem::size_of::<PackedVertex>() as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let index_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Arena Index Buffer"),
+            size: ARENA_INDICES as u64 * std::mem::size_of::<u16>() as u64,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let chunk_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Draw Buffer"),
+            size: MAX_CHUNKS as u64 * std::mem::size_of::<ChunkDraw>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let visibility_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Visibility Buffer"),
+            size: MAX_CHUNKS as u64 * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let cull_uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Cull Uniform Buffer"),
+            size: std::mem::size_of::<CullUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let draw_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Indirect Draw Buffer"),
+            size: MAX_CHUNKS as u64 * std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let cull_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, true),
+                storage_entry(3, false),
+            ],
+            label: Some("cull_bind_group_layout"),
+        });
+
+        let cull_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &cull_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: cull_uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: chunk_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: visibility_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: draw_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cull_bind_group"),
+        });
+
+        let cull_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Cull Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("cull.wgsl").into()),
+        });
+
+        let cull_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cull Pipeline Layout"),
+            bind_group_layouts: &[&cull_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let cull_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cull Pipeline"),
+            layout: Some(&cull_pipeline_layout),
+            module: &cull_shader,
+            entry_point: Some("cs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            chunk_buffer,
+            visibility_buffer,
+            cull_uniform_buffer,
+            draw_buffer,
+            cull_pipeline,
+            cull_bind_group,
+            vertex_ranges: RangeAllocator::new(ARENA_VERTICES),
+            index_ranges: RangeAllocator::new(ARENA_INDICES),
+            slots: HashMap::new(),
+            free_slots: Vec::new(),
+            slot_count: 0,
+            chunk_size,
+            multi_draw: device.features().contains(wgpu::Features::MULTI_DRAW_INDIRECT),
+        }
+    }
+
+    // Uploads newly loaded or remeshed chunks and releases the space of unloaded ones
+    pub fn sync(&mut self, queue: &wgpu::Queue, world: &World) {
+        // Remeshed chunks are released here and uploaded again below
+        let unloaded: Vec<(i32, i32)> = self.slots.iter()
+            .filter(|(pos, slot)| world.chunks.get(pos).is_none_or(|chunk| chunk.revision != slot.revision))
+            .map(|(pos, _)| *pos)
+            .collect();
+
+        for pos in unloaded {
+            let slot = self.slots.remove(&pos).unwrap();
+            self.vertex_ranges.free(slot.vertices);
+            self.index_ranges.free(slot.indices);
+            if let Some(translucent) = slot.translucent {
+                self.vertex_ranges.free(translucent.vertices);
+                self.index_ranges.free(translucent.indices);
+            }
+            self.write_chunk_draw(queue, slot.slot, &ChunkDraw::zeroed());
+            self.free_slots.push(slot.slot);
+        }
+
+        for (pos, chunk) in world.chunks.iter() {
+            if self.slots.contains_key(pos) || (chunk.vertices.is_empty() && chunk.translucent_vertices.is_empty()) {
+                continue;
+            }
+
+            let slot = match self.free_slots.pop() {
+                Some(slot) => slot,
+                None if self.slot_count < MAX_CHUNKS => {
+                    self.slot_count += 1;
+                    self.slot_count - 1
+                }
+                None => {
+                    println!("Chunk arena is out of draw slots, skipping chunk {:?}", pos);
+                    continue;
+                }
+            };
+
+            let Some((vertices, index_range)) = self.upload(queue, slot, &chunk.vertices, &chunk.indices) else {
+                println!("Chunk arena is out of space, skipping chunk {:?}", pos);
+                self.free_slots.push(slot);
+                continue;
+            };
+
+            let translucent = if chunk.translucent_vertices.is_empty() {
+                None
+            } else {
+                match self.upload(queue, slot, &chunk.translucent_vertices, &chunk.translucent_indices) {
+                    Some((vertices, indices)) => Some(TranslucentMesh::new(vertices, indices, &chunk.translucent_vertices, &chunk.translucent_indices)),
+                    None => {
+                        println!("Chunk arena is out of space, skipping translucent faces of chunk {:?}", pos);
+                        None
+                    }
+                }
+            };
+
+            // Bounds stay local to the chunk so they keep full precision far from the origin
+            let mut aabb_min = [f32::MAX; 3];
+            let mut aabb_max = [f32::MIN; 3];
+            for vertex in chunk.vertices.iter().chain(&chunk.translucent_vertices) {
+                let local = vertex.local_position();
+                for axis in 0..3 {
+                    aabb_min[axis] = aabb_min[axis].min(local[axis] as f32);
+                    aabb_max[axis] = aabb_max[axis].max(local[axis] as f32);
+                }
+            }
+
+            self.write_chunk_draw(queue, slot, &ChunkDraw {
+                chunk_pos: [pos.0, 0, pos.1, 0],
+                aabb_min: [aabb_min[0], aabb_min[1], aabb_min[2], 0.0],
+                aabb_max: [aabb_max[0], aabb_max[1], aabb_max[2], 0.0],
+                index_count: chunk.indices.len() as u32,
+                first_index: index_range.start,
+                base_vertex: vertices.start as i32,
+                _padding: 0,
+            });
+
+            self.slots.insert(*pos, ArenaSlot {
+                slot,
+                vertices,
+                indices: index_range,
+                index_count: chunk.indices.len() as u32,
+                translucent,
+                revision: chunk.revision,
+            });
+        }
+    }
+
+    // Copies one mesh into the arena, returning its vertex and index ranges or None when either buffer is full
+    fn upload(&mut self, queue: &wgpu::Queue, slot: u32, vertices: &[PackedVertex], indices: &[u16]) -> Option<(Range<u32>, Range<u32>)> {
+        // Index ranges stay an even length so every upload lands on a 4-byte boundary
+        let mut indices = indices.to_vec();
+        if !indices.len().is_multiple_of(2) {
+            indices.push(0);
+        }
+
+        let vertex_range = self.vertex_ranges.alloc(vertices.len() as u32);
+        let index_range = self.index_ranges.alloc(indices.len() as u32);
+        let (vertex_range, index_range) = match (vertex_range, index_range) {
+            (Some(vertex_range), Some(index_range)) => (vertex_range, index_range),
+            (vertex_range, index_range) => {
+                if let Some(vertex_range) = vertex_range {
+                    self.vertex_ranges.free(vertex_range);
+                }
+                if let Some(index_range) = index_range {
+                    self.index_ranges.free(index_range);
+                }
+                return None;
+            }
+        };
+
+        // The vertex shader finds the chunk position through the slot stored in each vertex
+        let mut packed = vertices.to_vec();
+        for vertex in packed.iter_mut() {
+            vertex.set_slot(slot);
+        }
+
+        if !packed.is_empty() {
+            queue.write_buffer(
+                &self.vertex_buffer,
+                vertex_range.start as u64 * std::mem::size_of::<PackedVertex>() as u64,
+                bytemuck::cast_slice(&packed),
+            );
+        }
+        if !indices.is_empty() {
+            queue.write_buffer(
+                &self.index_buffer,
+                index_range.start as u64 * std::mem::size_of::<u16>() as u64,
+                bytemuck::cast_slice(&indices),
+            );
+        }
+        Some((vertex_range, index_range))
+    }
+
+    // Rewrites the translucent indices of every chunk back to front from the camera. Faces only change
+    // order when the camera crosses a block boundary, so chunks already sorted from that block are skipped.
+    pub fn sort_translucent(&mut self, queue: &wgpu::Queue, camera: &Camera) {
+        let (camera_chunk, camera_offset) = camera.chunk_and_offset(self.chunk_size);
+        let size = self.chunk_size as f32;
+        let eye_block = [camera.eye.x.floor() as i32, camera.eye.y.floor() as i32, camera.eye.z.floor() as i32];
+
+        for (pos, slot) in self.slots.iter_mut() {
+            let Some(mesh) = slot.translucent.as_mut() else {
+                continue;
+            };
+            if mesh.sorted_from == Some(eye_block) {
+                continue;
+            }
+
+            // Camera position in the chunk's local space
+            let eye = [
+                (camera_chunk.0 - pos.0) as f32 * size + camera_offset[0],
+                camera_offset[1],
+                (camera_chunk.1 - pos.1) as f32 * size + camera_offset[2],
+            ];
+            let distances: Vec<f32> = mesh.centers.iter()
+                .map(|center| (0..3).map(|axis| (center[axis] - eye[axis]).powi(2)).sum())
+                .collect();
+            let mut order: Vec<usize> = (0..mesh.faces.len()).collect();
+            order.sort_by(|&a, &b| distances[b].total_cmp(&distances[a]));
+
+            let indices: Vec<u16> = order.iter().flat_map(|&face| mesh.faces[face]).collect();
+            queue.write_buffer(
+                &self.index_buffer,
+                mesh.indices.start as u64 * std::mem::size_of::<u16>() as u64,
+                bytemuck::cast_slice(&indices),
+            );
+            mesh.sorted_from = Some(eye_block);
+        }
+    }
+
+    fn write_chunk_draw(&self, queue: &wgpu::Queue, slot: u32, draw: &ChunkDraw) {
+        queue.write_buffer(
+            &self.chunk_buffer,
+            slot as u64 * std::mem::size_of::<ChunkDraw>() as u64,
+            bytemuck::cast_slice(&[*draw]),
+        );
+    }
+
+    // Per-slot chunk data, also read by the vertex shader for chunk positions
+    pub fn chunk_binding(&self) -> wgpu::BindingResource<'_> {
+        self.chunk_buffer.as_entire_binding()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    // Records the compute pass that frustum culls every slot and writes its draw arguments
+    pub fn cull(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, camera: &Camera, visible: &[(i32, i32)]) {
+        if self.slot_count == 0 {
+            return;
+        }
+
+        let mut visibility = vec![0u32; self.slot_count as usize];
+        for pos in visible {
+            if let Some(slot) = self.slots.get(pos) {
+                visibility[slot.slot as usize] = 1;
+            }
+        }
+        queue.write_buffer(&self.visibility_buffer, 0, bytemuck::cast_slice(&visibility));
+
+        let (camera_chunk, camera_offset) = camera.chunk_and_offset(self.chunk_size);
+        let cull_uniforms = CullUniforms {
+            planes: camera.frustum_planes(),
+            camera_chunk: [camera_chunk.0, 0, camera_chunk.1, 0],
+            camera_offset: [camera_offset[0], camera_offset[1], camera_offset[2], self.chunk_size as f32],
+            chunk_count: self.slot_count,
+            _padding: [0; 3],
+        };
+        queue.write_buffer(&self.cull_uniform_buffer, 0, bytemuck::cast_slice(&[cull_uniforms]));
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Cull Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.cull_pipeline);
+        compute_pass.set_bind_group(0, &self.cull_bind_group, &[]);
+        compute_pass.dispatch_workgroups(self.slot_count.div_ceil(CULL_WORKGROUP_SIZE), 1, 1);
+    }
+
+    // Draws every loaded chunk straight from the CPU-side ranges, ignoring the camera culling results.
+    // Used by passes that see chunks the camera doesn't, such as shadow casters behind the camera.
+    pub fn draw_all(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.slots.is_empty() {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        for slot in self.slots.values() {
+            let first_index = slot.indices.start;
+            render_pass.draw_indexed(first_index..first_index + slot.index_count, slot.vertices.start as i32, 0..1);
+        }
+    }
+
+    // Draws the translucent faces of the visible chunks, furthest chunk first. Call after everything
+    // opaque, with the faces already sorted by `sort_translucent`.
+    pub fn draw_translucent(&self, render_pass: &mut wgpu::RenderPass, camera: &Camera, visible: &[(i32, i32)]) {
+        let (camera_chunk, camera_offset) = camera.chunk_and_offset(self.chunk_size);
+        let size = self.chunk_size as f32;
+        let mut meshes: Vec<(f32, &TranslucentMesh)> = visible.iter()
+            .filter_map(|pos| {
+                let mesh = self.slots.get(pos)?.translucent.as_ref()?;
+                let dx = (pos.0 - camera_chunk.0) as f32 * size + size / 2.0 - camera_offset[0];
+                let dz = (pos.1 - camera_chunk.1) as f32 * size + size / 2.0 - camera_offset[2];
+                Some((dx * dx + dz * dz, mesh))
+            })
+            .collect();
+        if meshes.is_empty() {
+            return;
+        }
+        meshes.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        for (_, mesh) in meshes {
+            let first_index = mesh.indices.start;
+            render_pass.draw_indexed(first_index..first_index + mesh.faces.len() as u32 * 6, mesh.vertices.start as i32, 0..1);
+        }
+    }
+
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if self.slot_count == 0 {
+            return;
+        }
+
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+
+        if self.multi_draw {
+            render_pass.multi_draw_indexed_indirect(&self.draw_buffer, 0, self.slot_count);
+        } else {
+            let stride = std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as u64;
+            for slot in 0..self.slot_count {
+                render_pass.draw_indexed_indirect(&self.draw_buffer, slot as u64 * stride);
+            }
+        }
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::{HashSet, VecDeque};
+use crate::chunk::{block_index, CHUNK_HEIGHT};
+use crate::vertex::FACE_NORMALS;
+use crate::world::World;
+
+pub const MAX_LIGHT: u8 = 15;
+const DOWN: usize = 3; // Index of -Y in FACE_NORMALS
+
+// Each voxel stores skylight in the high nibble and block light in the low nibble
+#[derive(Copy, Clone, PartialEq)]
+enum LightChannel {
+    Sky,
+    Block,
+}
+
+const CHANNELS: [LightChannel; 2] = [LightChannel::Sky, LightChannel::Block];
+
+impl LightChannel {
+    fn get(self, packed: u8) -> u8 {
+        match self {
+            LightChannel::Sky => packed >> 4,
+            LightChannel::Block => packed & 0xF,
+        }
+    }
+
+    fn set(self, packed: u8, level: u8) -> u8 {
+        match self {
+            LightChannel::Sky => (packed & 0x0F) | level << 4,
+            LightChannel::Block => (packed & 0xF0) | level,
+        }
+    }
+
+    // Full skylight falls straight down without fading, everything else loses a level per step
+    fn spread(self, level: u8, face: usize) -> u8 {
+        if self == LightChannel::Sky && face == DOWN && level == MAX_LIGHT {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+}
+
+fn offset(position: [i32; 3], face: usize) -> [i32; 3] {
+    let normal = FACE_NORMALS[face];
+    [position[0] + normal[0], position[1] + normal[1], position[2] + normal[2]]
+}
+
+// Chunk and index of a world voxel, None when its chunk isn't loaded or it's outside the world height
+fn voxel(world: &World, position: [i32; 3]) -> Option<((i32, i32), usize)> {
+    if position[1] < 0 || position[1] >= CHUNK_HEIGHT as i32 {
+        return None;
+    }
+    let size = world.chunk_size as i32;
+    let chunk_pos = (position[0].div_euclid(size), position[2].div_euclid(size));
+    if !world.chunks.contains_key(&chunk_pos) {
+        return None;
+    }
+    let index = block_index(position[0].rem_euclid(size) as usize, position[1] as usize, position[2].rem_euclid(size) as usize, world.chunk_size);
+    Some((chunk_pos, index))
+}
+
+// Packed light at a world position. Above the world is open sky, below it and in unloaded chunks
+// (which will light themselves once they load) is treated the same as the surface.
+pub fn light_at(world: &World, position: [i32; 3]) -> u8 {
+    if position[1] < 0 {
+        return 0;
+    }
+    match voxel(world, position) {
+        Some((chunk_pos, index)) => world.chunks[&chunk_pos].light[index],
+        None => MAX_LIGHT << 4,
+    }
+}
+
+fn get_light(world: &World, position: [i32; 3], channel: LightChannel) -> Option<u8> {
+    voxel(world, position).map(|(chunk_pos, index)| channel.get(world.chunks[&chunk_pos].light[index]))
+}
+
+// Marks every chunk whose mesh samples this voxel: its own, and the neighbour across a border it touches
+fn set_light(world: &mut World, position: [i32; 3], channel: LightChannel, level: u8, dirty: &mut HashSet<(i32, i32)>) {
+    let Some((chunk_pos, index)) = voxel(world, position) else {
+        return;
+    };
+    let chunk = world.chunks.get_mut(&chunk_pos).unwrap();
+    chunk.light[index] = channel.set(chunk.light[index], level);
+
+    let size = world.chunk_size as i32;
+    let local = (position[0].rem_euclid(size), position[2].rem_euclid(size));
+    dirty.insert(chunk_pos);
+    if local.0 == 0 {
+        dirty.insert((chunk_pos.0 - 1, chunk_pos.1));
+    }
+    if local.0 == size - 1 {
+        dirty.insert((chunk_pos.0 + 1, chunk_pos.1));
+    }
+    if local.1 == 0 {
+        dirty.insert((chunk_pos.0, chunk_pos.1 - 1));
+    }
+    if local.1 == size - 1 {
+        dirty.insert((chunk_pos.0, chunk_pos.1 + 1));
+    }
+}
+
+fn passes_light(world: &World, position: [i32; 3]) -> bool {
+    world.block_at(position).is_some_and(|id| !world.registry.is_opaque(id))
+}
+
+fn emission(world: &World, position: [i32; 3]) -> u8 {
+    world.block_at(position)
+        .and_then(|id| world.registry.get(id))
+        .map_or(0, |block| block.light_emission.min(MAX_LIGHT))
+}
+
+// Breadth-first flood fill outward from every queued voxel
+fn propagate(world: &mut World, channel: LightChannel, mut queue: VecDeque<[i32; 3]>, dirty: &mut HashSet<(i32, i32)>) {
+    while let Some(position) = queue.pop_front() {
+        let Some(level) = get_light(world, position, channel) else {
+            continue;
+        };
+        for face in 0..6 {
+            let next_level = channel.spread(level, face);
+            if next_level == 0 {
+                continue;
+            }
+            let neighbour = offset(position, face);
+            if !passes_light(world, neighbour) {
+                continue;
+            }
+            if get_light(world, neighbour, channel).is_some_and(|current| current < next_level) {
+                set_light(world, neighbour, channel, next_level, dirty);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+}
+
+// Clears every voxel that was lit through the removed light, and collects the brighter voxels
+// around the cleared area so `propagate` can fill it back in from the light that is left
+fn unpropagate(world: &mut World, channel: LightChannel, mut queue: VecDeque<([i32; 3], u8)>, refill: &mut VecDeque<[i32; 3]>, dirty: &mut HashSet<(i32, i32)>) {
+    while let Some((position, level)) = queue.pop_front() {
+        for face in 0..6 {
+            let neighbour = offset(position, face);
+            let Some(neighbour_level) = get_light(world, neighbour, channel) else {
+                continue;
+            };
+            if neighbour_level == 0 {
+                continue;
+            }
+
+            let lit_by_removed = if channel == LightChannel::Sky && face == DOWN && level == MAX_LIGHT {
+                neighbour_level == MAX_LIGHT
+            } else {
+                neighbour_level < level
+            };
+            if lit_by_removed {
+                set_light(world, neighbour, channel, 0, dirty);
+                queue.push_back((neighbour, neighbour_level));
+
+                // Light sources inside the cleared area shine again
+                if channel == LightChannel::Block {
+                    let source = emission(world, neighbour);
+                    if source > 0 {
+                        set_light(world, neighbour, channel, source, dirty);
+                        refill.push_back(neighbour);
+                    }
+                }
+            } else {
+                refill.push_back(neighbour);
+            }
+        }
+    }
+}
+
+// Lights a freshly loaded chunk: skylight down every open column, block light from emissive blocks,
+// and light flowing in from the borders of loaded neighbours. Returns the chunks that need remeshing.
+pub fn light_chunk(world: &mut World, chunk_pos: (i32, i32)) -> HashSet<(i32, i32)> {
+    let mut dirty = HashSet::new();
+    dirty.insert(chunk_pos);
+
+    let size = world.chunk_size as i32;
+    let origin = [chunk_pos.0 * size, 0, chunk_pos.1 * size];
+    let mut sky_queue = VecDeque::new();
+    let mut block_queue = VecDeque::new();
+
+    for z in 0..size {
+        for x in 0..size {
+            for y in (0..CHUNK_HEIGHT as i32).rev() {
+                let position = [origin[0] + x, y, origin[2] + z];
+                if !passes_light(world, position) {
+                    break;
+                }
+                set_light(world, position, LightChannel::Sky, MAX_LIGHT, &mut dirty);
+                sky_queue.push_back(position);
+            }
+
+            for y in 0..CHUNK_HEIGHT as i32 {
+                let position = [origin[0] + x, y, origin[2] + z];
+                let source = emission(world, position);
+                if source > 0 {
+                    set_light(world, position, LightChannel::Block, source, &mut dirty);
+                    block_queue.push_back(position);
+                }
+            }
+        }
+    }
+
+    // The voxels just outside each border belong to neighbours, they are skipped if not loaded
+    for i in 0..size {
+        for y in 0..CHUNK_HEIGHT as i32 {
+            for position in [
+                [origin[0] - 1, y, origin[2] + i],
+                [origin[0] + size, y, origin[2] + i],
+                [origin[0] + i, y, origin[2] - 1],
+                [origin[0] + i, y, origin[2] + size],
+            ] {
+                if voxel(world, position).is_some() {
+                    sky_queue.push_back(position);
+                    block_queue.push_back(position);
+                }
+            }
+        }
+    }
+
+    propagate(world, LightChannel::Sky, sky_queue, &mut dirty);
+    propagate(world, LightChannel::Block, block_queue, &mut dirty);
+    dirty.retain(|pos| world.chunks.contains_key(pos));
+    dirty
+}
+
+// Relights around a block that was just placed or removed (the new id is already stored).
+// Returns the chunks that need remeshing.
+pub fn update_block_light(world: &mut World, position: [i32; 3]) -> HashSet<(i32, i32)> {
+    let mut dirty = HashSet::new();
+
+    for channel in CHANNELS {
+        let Some(old_level) = get_light(world, position, channel) else {
+            continue;
+        };
+
+        // Take away whatever light the voxel had, along with everything it lit
+        let mut refill = VecDeque::new();
+        set_light(world, position, channel, 0, &mut dirty);
+        unpropagate(world, channel, VecDeque::from([(position, old_level)]), &mut refill, &mut dirty);
+
+        // Then seed the new block's own light and let the neighbours shine back in
+        let seed = match channel {
+            LightChannel::Sky if position[1] == CHUNK_HEIGHT as i32 - 1 && passes_light(world, position) => MAX_LIGHT,
+            LightChannel::Sky => 0,
+            LightChannel::Block => emission(world, position),
+        };
+        if seed > 0 {
+            set_light(world, position, channel, seed, &mut dirty);
+            refill.push_back(position);
+        }
+        for face in 0..6 {
+            refill.push_back(offset(position, face));
+        }
+        propagate(world, channel, refill, &mut dirty);
+    }
+
+    dirty.retain(|pos| world.chunks.contains_key(pos));
+    dirty
+}
@@ -0,0 +1,10 @@
+use cgmath::Vector3;
+
+// A single directional light plus a flat ambient term, shaded per face with Lambert's cosine law.
+// day_night::WorldClock produces one for the current time of day.
+#[derive(Copy, Clone)]
+pub struct SunLight {
+    pub direction: Vector3<f32>, // Points from the ground towards the sun
+    pub color: [f32; 3],
+    pub ambient: [f32; 3], // Light that reaches faces turned away from the sun
+}
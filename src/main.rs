@@ -15,6 +15,27 @@ mod chunk;
 mod world;
 mod world_update;
 mod texture;
+mod occlusion;
+mod indirect;
+mod instancing;
+mod block;
+mod pipeline;
+mod hot_reload;
+mod sky;
+mod fog;
+mod lighting;
+mod shadow;
+mod day_night;
+mod light;
+mod render_targets;
+mod post;
+mod post_effects;
+mod ssao;
+mod point_light;
+mod present;
+mod headless;
+#[cfg(test)]
+mod golden;
 
 #[derive(Default)]
 struct App {
@@ -41,6 +62,15 @@ impl ApplicationHandler for App {
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
+    // `--render <path>` writes a PNG of the spawn view without opening a window
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--render") {
+        let path = args.get(2).map(String::as_str).unwrap_or("render.png");
+        pollster::block_on(headless::render_thumbnail(path))?;
+        println!("Rendered {}", path);
+        return Ok(());
+    }
+
     // Correctly create the event loop
     let event_loop = EventLoop::new().unwrap();
     let mut app = App::default();
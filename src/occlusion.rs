@@ -0,0 +1,176 @@
+use std::collections::{HashMap, VecDeque};
+use crate::block::BlockRegistry;
+use crate::camera::Camera;
+use crate::chunk::{block_index, CHUNK_HEIGHT};
+use crate::world::World;
+
+// Chunk faces, used as bit positions in the masks below
+pub const POS_X: usize = 0;
+pub const NEG_X: usize = 1;
+pub const POS_Y: usize = 2;
+pub const NEG_Y: usize = 3;
+pub const POS_Z: usize = 4;
+pub const NEG_Z: usize = 5;
+
+const ALL_FACES: u8 = 0b11_1111;
+
+// Faces the traversal can step through, with the chunk offset they lead to
+const HORIZONTAL_STEPS: [(usize, (i32, i32)); 4] = [
+    (POS_X, (1, 0)),
+    (NEG_X, (-1, 0)),
+    (POS_Z, (0, 1)),
+    (NEG_Z, (0, -1)),
+];
+
+fn opposite(face: usize) -> usize {
+    face ^ 1
+}
+
+// Records which pairs of chunk faces can see each other through non-opaque blocks
+#[derive(Copy, Clone, Default)]
+pub struct ChunkVisibility {
+    connections: u64,
+}
+
+impl ChunkVisibility {
+    fn connect(&mut self, a: usize, b: usize) {
+        self.connections |= 1 << (a * 6 + b);
+        self.connections |= 1 << (b * 6 + a);
+    }
+
+    pub fn is_connected(&self, a: usize, b: usize) -> bool {
+        self.connections & (1 << (a * 6 + b)) != 0
+    }
+
+    fn any_connected(&self, from_mask: u8, to: usize) -> bool {
+        (0..6).any(|face| from_mask & (1 << face) != 0 && self.is_connected(face, to))
+    }
+}
+
+pub fn compute_visibility(blocks: &[u16], chunk_size: usize, registry: &BlockRegistry) -> ChunkVisibility {
+    let mut visibility = ChunkVisibility::default();
+    let opaque: Vec<bool> = blocks.iter().map(|&id| registry.is_opaque(id)).collect();
+    let mut visited = vec![false; blocks.len()];
+    let mut stack = Vec::new();
+
+    for start in 0..blocks.len() {
+        if opaque[start] || visited[start] {
+            continue;
+        }
+
+        // Flood fill one see-through pocket and note every chunk face it touches
+        let mut touched: u8 = 0;
+        visited[start] = true;
+        stack.push(start);
+        while let Some(index) = stack.pop() {
+            let x = index % chunk_size;
+            let z = (index / chunk_size) % chunk_size;
+            let y = index / (chunk_size * chunk_size);
+
+            let mut neighbours = Vec::with_capacity(6);
+            if x + 1 < chunk_size {
+                neighbours.push((x + 1, y, z));
+            } else {
+                touched |= 1 << POS_X;
+            }
+            if x > 0 {
+                neighbours.push((x - 1, y, z));
+            } else {
+                touched |= 1 << NEG_X;
+            }
+            if y + 1 < CHUNK_HEIGHT {
+                neighbours.push((x, y + 1, z));
+            } else {
+                touched |= 1 << POS_Y;
+            }
+            if y > 0 {
+                neighbours.push((x, y - 1, z));
+            } else {
+                touched |= 1 << NEG_Y;
+            }
+            if z + 1 < chunk_size {
+                neighbours.push((x, y, z + 1));
+            } else {
+                touched |= 1 << POS_Z;
+            }
+            if z > 0 {
+                neighbours.push((x, y, z - 1));
+            } else {
+                touched |= 1 << NEG_Z;
+            }
+
+            for (nx, ny, nz) in neighbours {
+                let neighbour = block_index(nx, ny, nz, chunk_size);
+                if !opaque[neighbour] && !visited[neighbour] {
+                    visited[neighbour] = true;
+                    stack.push(neighbour);
+                }
+            }
+        }
+
+        for a in 0..6 {
+            for b in 0..6 {
+                if touched & (1 << a) != 0 && touched & (1 << b) != 0 {
+                    visibility.connect(a, b);
+                }
+            }
+        }
+    }
+
+    visibility
+}
+
+pub fn visible_chunks(world: &World, camera: &Camera) -> Vec<(i32, i32)> {
+    let camera_chunk_pos = (
+        (camera.eye.x / (world.chunk_size as f32)).floor() as i32,
+        (camera.eye.z / (world.chunk_size as f32)).floor() as i32,
+    );
+
+    // Without a chunk to start from there is nothing to walk, so draw everything
+    if !world.chunks.contains_key(&camera_chunk_pos) {
+        return world.chunks.keys().cloned().collect();
+    }
+
+    // Entry face mask per reached chunk, so a wider entry can revisit a chunk
+    let mut reached: HashMap<(i32, i32), u8> = HashMap::new();
+    let mut queue = VecDeque::new();
+    reached.insert(camera_chunk_pos, ALL_FACES);
+    queue.push_back((camera_chunk_pos, ALL_FACES, 0u8));
+
+    while let Some((pos, entry_mask, directions)) = queue.pop_front() {
+        let visibility = world.chunks[&pos].visibility;
+
+        for (face, offset) in HORIZONTAL_STEPS {
+            // Never walk back towards the camera
+            if directions & (1 << opposite(face)) != 0 {
+                continue;
+            }
+
+            let neighbour_pos = (pos.0 + offset.0, pos.1 + offset.1);
+            if !world.chunks.contains_key(&neighbour_pos) {
+                continue;
+            }
+
+            // Either look straight through the shared face, or out the top and down over it
+            let mut neighbour_entry = 0;
+            if visibility.any_connected(entry_mask, face) {
+                neighbour_entry |= 1 << opposite(face);
+            }
+            if visibility.any_connected(entry_mask, POS_Y) {
+                neighbour_entry |= 1 << POS_Y;
+            }
+            if neighbour_entry == 0 {
+                continue;
+            }
+
+            let known_entry = reached.get(&neighbour_pos).copied().unwrap_or(0);
+            if known_entry | neighbour_entry == known_entry {
+                continue;
+            }
+            reached.insert(neighbour_pos, known_entry | neighbour_entry);
+            queue.push_back((neighbour_pos, known_entry | neighbour_entry, directions | (1 << face)));
+        }
+    }
+
+    reached.into_keys().collect()
+}
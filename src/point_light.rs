@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, Point3, SquareMatrix};
+use crate::camera::Camera;
+
+pub const MAX_POINT_LIGHTS: usize = 1024;
+pub const MAX_LIGHTS_PER_CLUSTER: usize = 64; // Must match cluster.wgsl and shader.wgsl
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24]; // Clusters along x, y and z
+const CLUSTER_COUNT: u32 = CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2];
+const CLUSTER_WORKGROUP_SIZE: u32 = 64;
+
+// A light spawned by gameplay code, e.g. a torch, a lantern or a glowing projectile
+#[derive(Copy, Clone)]
+pub struct PointLight {
+    pub position: Point3<f32>,
+    pub color: [f32; 3],
+    pub radius: f32, // Nothing past this distance is lit
+    pub intensity: f32,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PointLightId(u32);
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct GpuPointLight {
+    position_radius: [f32; 4], // Camera-relative
+    color_intensity: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ClusterUniforms {
+    view: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    grid: [u32; 4], // Clusters along x, y and z, light count
+    params: [f32; 4], // Near, far, screen width, screen height
+}
+
+// Every live point light, uploaded each frame and binned into view-space clusters by a compute
+// pass so fs_main only loops over the lights that can reach each pixel
+pub struct PointLights {
+    lights: HashMap<PointLightId, PointLight>,
+    next_id: u32,
+    light_buffer: wgpu::Buffer,
+    cluster_buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    cluster_pipeline: wgpu::ComputePipeline,
+    cluster_bind_group: wgpu::BindGroup,
+}
+
+impl PointLights {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let light_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Point Light Buffer"),
+            size: (MAX_POINT_LIGHTS * std::mem::size_of::<GpuPointLight>()) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        // One light count plus MAX_LIGHTS_PER_CLUSTER indices per cluster
+        let cluster_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Cluster Buffer"),
+            size: CLUSTER_COUNT as u64 * (1 + MAX_LIGHTS_PER_CLUSTER as u64) * std::mem::size_of::<u32>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Light Cluster Uniform Buffer"),
+            size: std::mem::size_of::<ClusterUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let storage_entry = |binding: u32, read_only: bool| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let cluster_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage_entry(1, true),
+                storage_entry(2, false),
+            ],
+            label: Some("cluster_bind_group_layout"),
+        });
+
+        let cluster_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &cluster_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: light_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: cluster_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("cluster_bind_group"),
+        });
+
+        let cluster_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Cluster Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("cluster.wgsl").into()),
+        });
+
+        let cluster_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Cluster Pipeline Layout"),
+            bind_group_layouts: &[&cluster_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let cluster_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Cluster Pipeline"),
+            layout: Some(&cluster_pipeline_layout),
+            module: &cluster_shader,
+            entry_point: Some("cs_main"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            cache: None,
+        });
+
+        Self {
+            lights: HashMap::new(),
+            next_id: 0,
+            light_buffer,
+            cluster_buffer,
+            uniform_buffer,
+            cluster_pipeline,
+            cluster_bind_group,
+        }
+    }
+
+    // Returns None once MAX_POINT_LIGHTS are alive
+    pub fn spawn(&mut self, light: PointLight) -> Option<PointLightId> {
+        if self.lights.len() >= MAX_POINT_LIGHTS {
+            return None;
+        }
+        let id = PointLightId(self.next_id);
+        self.next_id = self.next_id.wrapping_add(1);
+        self.lights.insert(id, light);
+        Some(id)
+    }
+
+    pub fn remove(&mut self, id: PointLightId) -> Option<PointLight> {
+        self.lights.remove(&id)
+    }
+
+    // For lights that move or flicker, e.g. a projectile following its path each frame
+    pub fn get_mut(&mut self, id: PointLightId) -> Option<&mut PointLight> {
+        self.lights.get_mut(&id)
+    }
+
+    // Lets gameplay code check the MAX_POINT_LIGHTS budget before `spawn` returns None
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    // Bound into the main bind group at 7, 8 and 9 for fs_main
+    pub fn light_binding(&self) -> wgpu::BindingResource<'_> {
+        self.light_buffer.as_entire_binding()
+    }
+
+    pub fn cluster_binding(&self) -> wgpu::BindingResource<'_> {
+        self.cluster_buffer.as_entire_binding()
+    }
+
+    pub fn uniform_binding(&self) -> wgpu::BindingResource<'_> {
+        self.uniform_buffer.as_entire_binding()
+    }
+
+    // Uploads the lights relative to the camera and records the compute pass that bins them
+    pub fn cull(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, camera: &Camera, screen_size: (u32, u32)) {
+        let eye = camera.eye;
+        let lights: Vec<GpuPointLight> = self.lights.values()
+            .map(|light| GpuPointLight {
+                position_radius: [light.position.x - eye.x, light.position.y - eye.y, light.position.z - eye.z, light.radius],
+                color_intensity: [light.color[0], light.color[1], light.color[2], light.intensity],
+            })
+            .collect();
+        if !lights.is_empty() {
+            queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&lights));
+        }
+
+        let inverse_projection = camera.build_projection_matrix().invert().unwrap_or(Matrix4::identity());
+        let uniforms = ClusterUniforms {
+            view: camera.build_view_matrix().into(),
+            inverse_projection: inverse_projection.into(),
+            grid: [CLUSTER_GRID[0], CLUSTER_GRID[1], CLUSTER_GRID[2], lights.len() as u32],
+            params: [camera.znear, camera.zfar, screen_size.0 as f32, screen_size.1 as f32],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+
+        // Binning runs even without lights so every cluster count is reset to zero
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Light Cluster Pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.cluster_pipeline);
+        compute_pass.set_bind_group(0, &self.cluster_bind_group, &[]);
+        compute_pass.dispatch_workgroups(CLUSTER_COUNT.div_ceil(CLUSTER_WORKGROUP_SIZE), 1, 1);
+    }
+}
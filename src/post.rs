@@ -0,0 +1,355 @@
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+use crate::post_effects::{Bloom, ColorGrading, Fxaa, Gamma, Tonemap};
+use crate::render_targets::HDR_FORMAT;
+use crate::ssao::SsaoSettings;
+
+// Vertex shader and bindings every post-processing pass starts from
+pub const FULLSCREEN_SHADER: &str = include_str!("fullscreen.wgsl");
+
+// One step of the chain. Effects read `input` and write all of `output`, both HDR_FORMAT textures
+// the size of the window. Custom effects implement this and are added with `PostChain::push`.
+pub trait PostEffect {
+    fn name(&self) -> &str;
+
+    // Called whenever the window size changes, for effects that keep their own textures
+    fn resize(&mut self, _device: &wgpu::Device, _width: u32, _height: u32) {}
+
+    // Effects that gamma-encode colors are skipped when the window's format is sRGB, since the
+    // hardware already encodes on write and they would encode twice
+    fn encodes_gamma(&self) -> bool {
+        false
+    }
+
+    fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, input: &wgpu::TextureView, output: &wgpu::TextureView);
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct PostParams {
+    values: [f32; 4],
+}
+
+// A single fullscreen draw with its own pipeline, params and sampler. Most effects are one of
+// these, `fragment_source` only has to define the fragment entry point (see fullscreen.wgsl).
+pub struct FullscreenPass {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    sampler: wgpu::Sampler,
+    label: String,
+}
+
+impl FullscreenPass {
+    // `extra` is the view dimension of the texture the effect binds at 3, if it uses one
+    pub fn new(
+        device: &wgpu::Device,
+        label: &str,
+        fragment_source: &str,
+        entry_point: &str,
+        extra: Option<wgpu::TextureViewDimension>,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let texture_entry = |binding: u32, view_dimension: wgpu::TextureViewDimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
+        };
+
+        let mut entries = vec![
+            texture_entry(0, wgpu::TextureViewDimension::D2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+        if let Some(view_dimension) = extra {
+            entries.push(texture_entry(3, view_dimension));
+        }
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &entries,
+            label: Some(label),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(format!("{}\n{}", FULLSCREEN_SHADER, fragment_source).into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(label),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_fullscreen"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: std::mem::size_of::<PostParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self { pipeline, bind_group_layout, params_buffer, sampler, label: label.to_string() }
+    }
+
+    pub fn set_params(&self, queue: &wgpu::Queue, values: [f32; 4]) {
+        queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[PostParams { values }]));
+    }
+
+    // The bind group is rebuilt every draw since the chain swaps which texture is the input
+    pub fn draw(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, input: &wgpu::TextureView, extra: Option<&wgpu::TextureView>, output: &wgpu::TextureView) {
+        let bind_group = self.create_bind_group(device, input, extra);
+        self.draw_with(encoder, &bind_group, output);
+    }
+
+    pub fn create_bind_group(&self, device: &wgpu::Device, input: &wgpu::TextureView, extra: Option<&wgpu::TextureView>) -> wgpu::BindGroup {
+        let mut entries = vec![
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(input),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: self.params_buffer.as_entire_binding(),
+            },
+        ];
+        if let Some(extra) = extra {
+            entries.push(wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(extra),
+            });
+        }
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &entries,
+            label: Some(&self.label),
+        })
+    }
+
+    // Draws with a bind group kept from create_bind_group, for passes whose inputs only change on resize
+    pub fn draw_with(&self, encoder: &mut wgpu::CommandEncoder, bind_group: &wgpu::BindGroup, output: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&self.label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: output,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+pub fn create_hdr_texture(device: &wgpu::Device, label: &str, width: u32, height: u32) -> wgpu::TextureView {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: HDR_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    }).create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// One entry of src/post.toml, in the order the passes run
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EffectConfig {
+    Bloom {
+        #[serde(default = "default_bloom_threshold")]
+        threshold: f32,
+        #[serde(default = "default_bloom_intensity")]
+        intensity: f32,
+    },
+    Tonemap {
+        #[serde(default = "default_exposure")]
+        exposure: f32,
+    },
+    ColorGrading {
+        lut: Option<String>, // Identity when unset
+        #[serde(default = "default_strength")]
+        strength: f32,
+    },
+    Gamma {
+        #[serde(default = "default_gamma")]
+        gamma: f32,
+    },
+    Fxaa,
+}
+
+fn default_bloom_threshold() -> f32 { 1.0 }
+fn default_bloom_intensity() -> f32 { 0.3 }
+fn default_exposure() -> f32 { 1.0 }
+fn default_strength() -> f32 { 1.0 }
+fn default_gamma() -> f32 { 2.2 }
+
+#[derive(Deserialize)]
+pub struct PostConfig {
+    #[serde(default)]
+    pub ssao: SsaoSettings,
+    #[serde(default, rename = "effect")]
+    pub effects: Vec<EffectConfig>,
+}
+
+impl PostConfig {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read post-processing config {}: {}", path, e))?;
+        toml::from_str(&source)
+            .map_err(|e| format!("Failed to load post-processing config {}: {}", path, e))
+    }
+}
+
+// Runs the effects in order on the HDR scene, ping-ponging between two intermediate textures,
+// then copies the result into the window
+pub struct PostChain {
+    effects: Vec<Box<dyn PostEffect>>,
+    targets: [wgpu::TextureView; 2],
+    present: FullscreenPass,
+    output_srgb: bool,
+    width: u32,
+    height: u32,
+}
+
+impl PostChain {
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat, width: u32, height: u32) -> Self {
+        Self {
+            effects: Vec::new(),
+            targets: Self::create_targets(device, width, height),
+            present: FullscreenPass::new(device, "Post Present", "", "fs_copy", None, format),
+            output_srgb: format.is_srgb(),
+            width,
+            height,
+        }
+    }
+
+    pub fn from_config(device: &wgpu::Device, queue: &wgpu::Queue, config: &PostConfig, format: wgpu::TextureFormat, width: u32, height: u32) -> Result<Self, String> {
+        let mut chain = Self::new(device, format, width, height);
+        for effect in &config.effects {
+            let effect: Box<dyn PostEffect> = match effect {
+                EffectConfig::Bloom { threshold, intensity } => Box::new(Bloom::new(device, queue, *threshold, *intensity)),
+                EffectConfig::Tonemap { exposure } => Box::new(Tonemap::new(device, queue, *exposure)),
+                EffectConfig::ColorGrading { lut, strength } => Box::new(ColorGrading::new(device, queue, lut.as_deref(), *strength)?),
+                EffectConfig::Gamma { gamma } => Box::new(Gamma::new(device, queue, *gamma)),
+                EffectConfig::Fxaa => Box::new(Fxaa::new(device, queue)),
+            };
+            chain.push(device, effect);
+        }
+        Ok(chain)
+    }
+
+    fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> [wgpu::TextureView; 2] {
+        [
+            create_hdr_texture(device, "Post Target A", width, height),
+            create_hdr_texture(device, "Post Target B", width, height),
+        ]
+    }
+
+    // Appends an effect to the end of the chain, sizing it to the window first
+    pub fn push(&mut self, device: &wgpu::Device, mut effect: Box<dyn PostEffect>) {
+        effect.resize(device, self.width, self.height);
+        self.effects.push(effect);
+    }
+
+    pub fn effect_names(&self) -> Vec<&str> {
+        self.effects.iter().map(|effect| effect.name()).collect()
+    }
+
+    // Rebuilds the final copy for a window switched to another surface format
+    pub fn set_output_format(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat) {
+        self.present = FullscreenPass::new(device, "Post Present", "", "fs_copy", None, format);
+        self.output_srgb = format.is_srgb();
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.width = width;
+        self.height = height;
+        self.targets = Self::create_targets(device, width, height);
+        for effect in self.effects.iter_mut() {
+            effect.resize(device, width, height);
+        }
+    }
+
+    // Records every pass, reading the resolved HDR scene and ending in `output`
+    pub fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, scene: &wgpu::TextureView, output: &wgpu::TextureView) {
+        let mut input = scene;
+        let effects = self.effects.iter().filter(|effect| !(self.output_srgb && effect.encodes_gamma()));
+        for (i, effect) in effects.enumerate() {
+            let target = &self.targets[i % 2];
+            effect.render(device, encoder, input, target);
+            input = target;
+        }
+        self.present.draw(device, encoder, input, None, output);
+    }
+}
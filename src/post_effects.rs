@@ -0,0 +1,230 @@
+use crate::post::{FullscreenPass, PostEffect, create_hdr_texture};
+use crate::render_targets::HDR_FORMAT;
+
+const IDENTITY_LUT_SIZE: u32 = 16;
+
+// Maps HDR scene colors into [0, 1] with the ACES filmic curve
+pub struct Tonemap {
+    pass: FullscreenPass,
+}
+
+impl Tonemap {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, exposure: f32) -> Self {
+        let pass = FullscreenPass::new(device, "Tonemap Pass", include_str!("tonemap.wgsl"), "fs_main", None, HDR_FORMAT);
+        pass.set_params(queue, [exposure, 0.0, 0.0, 0.0]);
+        Self { pass }
+    }
+
+    pub fn set_exposure(&self, queue: &wgpu::Queue, exposure: f32) {
+        self.pass.set_params(queue, [exposure, 0.0, 0.0, 0.0]);
+    }
+}
+
+impl PostEffect for Tonemap {
+    fn name(&self) -> &str {
+        "tonemap"
+    }
+
+    fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        self.pass.draw(device, encoder, input, None, output);
+    }
+}
+
+// Encodes linear color for a display expecting gamma-encoded values
+pub struct Gamma {
+    pass: FullscreenPass,
+}
+
+impl Gamma {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, gamma: f32) -> Self {
+        let pass = FullscreenPass::new(device, "Gamma Pass", include_str!("gamma.wgsl"), "fs_main", None, HDR_FORMAT);
+        pass.set_params(queue, [gamma, 0.0, 0.0, 0.0]);
+        Self { pass }
+    }
+}
+
+impl PostEffect for Gamma {
+    fn name(&self) -> &str {
+        "gamma"
+    }
+
+    fn encodes_gamma(&self) -> bool {
+        true
+    }
+
+    fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        self.pass.draw(device, encoder, input, None, output);
+    }
+}
+
+pub struct Fxaa {
+    pass: FullscreenPass,
+}
+
+impl Fxaa {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let pass = FullscreenPass::new(device, "FXAA Pass", include_str!("fxaa.wgsl"), "fs_main", None, HDR_FORMAT);
+        pass.set_params(queue, [0.0; 4]);
+        Self { pass }
+    }
+}
+
+impl PostEffect for Fxaa {
+    fn name(&self) -> &str {
+        "fxaa"
+    }
+
+    fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        self.pass.draw(device, encoder, input, None, output);
+    }
+}
+
+// Bright parts of the scene bleed into their surroundings. The bright pass and the blur run at
+// half resolution, then the blurred result is added back onto the full-size scene.
+pub struct Bloom {
+    bright: FullscreenPass,
+    blur_horizontal: FullscreenPass,
+    blur_vertical: FullscreenPass,
+    composite: FullscreenPass,
+    targets: [wgpu::TextureView; 2],
+}
+
+impl Bloom {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, threshold: f32, intensity: f32) -> Self {
+        let source = include_str!("bloom.wgsl");
+        let bright = FullscreenPass::new(device, "Bloom Bright Pass", source, "fs_bright", None, HDR_FORMAT);
+        let blur_horizontal = FullscreenPass::new(device, "Bloom Horizontal Blur Pass", source, "fs_blur", None, HDR_FORMAT);
+        let blur_vertical = FullscreenPass::new(device, "Bloom Vertical Blur Pass", source, "fs_blur", None, HDR_FORMAT);
+        let composite = FullscreenPass::new(device, "Bloom Composite Pass", source, "fs_composite", Some(wgpu::TextureViewDimension::D2), HDR_FORMAT);
+
+        bright.set_params(queue, [threshold, 0.0, 0.0, 0.0]);
+        blur_horizontal.set_params(queue, [1.0, 0.0, 0.0, 0.0]);
+        blur_vertical.set_params(queue, [0.0, 1.0, 0.0, 0.0]);
+        composite.set_params(queue, [intensity, 0.0, 0.0, 0.0]);
+
+        // Sized for real by PostChain::push
+        let targets = Self::create_targets(device, 1, 1);
+        Self { bright, blur_horizontal, blur_vertical, composite, targets }
+    }
+
+    fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> [wgpu::TextureView; 2] {
+        [
+            create_hdr_texture(device, "Bloom Target A", width / 2, height / 2),
+            create_hdr_texture(device, "Bloom Target B", width / 2, height / 2),
+        ]
+    }
+}
+
+impl PostEffect for Bloom {
+    fn name(&self) -> &str {
+        "bloom"
+    }
+
+    fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.targets = Self::create_targets(device, width, height);
+    }
+
+    fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        let [a, b] = &self.targets;
+        self.bright.draw(device, encoder, input, None, a);
+        self.blur_horizontal.draw(device, encoder, a, None, b);
+        self.blur_vertical.draw(device, encoder, b, None, a);
+        self.composite.draw(device, encoder, input, Some(a), output);
+    }
+}
+
+// Looks every color up in a 3D LUT. LUT images are the usual horizontal strip of `size` slices,
+// each `size` pixels square, with red along x, green along y and blue picking the slice.
+pub struct ColorGrading {
+    pass: FullscreenPass,
+    lut_view: wgpu::TextureView,
+}
+
+impl ColorGrading {
+    // Without a LUT image an identity LUT is used, which leaves colors unchanged
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, lut_path: Option<&str>, strength: f32) -> Result<Self, String> {
+        let (size, texels) = match lut_path {
+            Some(path) => load_lut(path)?,
+            None => (IDENTITY_LUT_SIZE, identity_lut(IDENTITY_LUT_SIZE)),
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Color Grading LUT"),
+            size: wgpu::Extent3d {
+                width: size,
+                height: size,
+                depth_or_array_layers: size,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &texels,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * size),
+                rows_per_image: Some(size),
+            },
+            texture.size(),
+        );
+        let lut_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let pass = FullscreenPass::new(device, "Color Grading Pass", include_str!("color_grading.wgsl"), "fs_main", Some(wgpu::TextureViewDimension::D3), HDR_FORMAT);
+        pass.set_params(queue, [size as f32, strength, 0.0, 0.0]);
+        Ok(Self { pass, lut_view })
+    }
+}
+
+impl PostEffect for ColorGrading {
+    fn name(&self) -> &str {
+        "color_grading"
+    }
+
+    fn render(&self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, input: &wgpu::TextureView, output: &wgpu::TextureView) {
+        self.pass.draw(device, encoder, input, Some(&self.lut_view), output);
+    }
+}
+
+// RGBA texels in x, then y, then slice order, as the 3D texture expects them
+fn identity_lut(size: u32) -> Vec<u8> {
+    let scale = 255.0 / (size - 1) as f32;
+    let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                texels.extend_from_slice(&[(r as f32 * scale).round() as u8, (g as f32 * scale).round() as u8, (b as f32 * scale).round() as u8, 255]);
+            }
+        }
+    }
+    texels
+}
+
+fn load_lut(path: &str) -> Result<(u32, Vec<u8>), String> {
+    let image = image::open(path)
+        .map_err(|e| format!("Failed to open LUT image {}: {}", path, e))?
+        .to_rgba8();
+    let size = image.height();
+    if size < 2 || image.width() != size * size {
+        return Err(format!("LUT image {} must be {} pixels wide for its height of {}", path, size * size, size));
+    }
+
+    let mut texels = Vec::with_capacity((size * size * size * 4) as usize);
+    for b in 0..size {
+        for g in 0..size {
+            for r in 0..size {
+                texels.extend_from_slice(&image.get_pixel(b * size + r, g).0);
+            }
+        }
+    }
+    Ok((size, texels))
+}
@@ -0,0 +1,111 @@
+use std::time::{Duration, Instant};
+
+// Set these environment variables to pick the starting present mode (fifo, mailbox, immediate or
+// auto_vsync) and to cap the frame rate (frames per second, 0 or unset for no cap)
+pub const PRESENT_MODE_ENV: &str = "MUTETRA_PRESENT_MODE";
+pub const FPS_CAP_ENV: &str = "MUTETRA_FPS_CAP";
+
+const PRESENT_MODES: [wgpu::PresentMode; 4] = [
+    wgpu::PresentMode::Fifo,
+    wgpu::PresentMode::Mailbox,
+    wgpu::PresentMode::Immediate,
+    wgpu::PresentMode::AutoVsync,
+];
+
+// Sleeping is only accurate to a millisecond or two, the rest of the wait is spun
+const SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+// Present modes from PRESENT_MODES the surface supports. AutoVsync falls back to Fifo, which every
+// surface has, so it is always available.
+pub fn supported_present_modes(capabilities: &wgpu::SurfaceCapabilities) -> Vec<wgpu::PresentMode> {
+    PRESENT_MODES.into_iter()
+        .filter(|mode| *mode == wgpu::PresentMode::AutoVsync || capabilities.present_modes.contains(mode))
+        .collect()
+}
+
+// The requested mode when supported, Fifo otherwise
+pub fn pick_present_mode(requested: wgpu::PresentMode, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    if supported.contains(&requested) { requested } else { wgpu::PresentMode::Fifo }
+}
+
+// The starting mode from MUTETRA_PRESENT_MODE, or Mailbox when unset or not recognised
+pub fn requested_present_mode() -> wgpu::PresentMode {
+    match std::env::var(PRESENT_MODE_ENV).ok().as_deref().map(str::trim) {
+        Some("fifo") => wgpu::PresentMode::Fifo,
+        Some("immediate") => wgpu::PresentMode::Immediate,
+        Some("auto_vsync") => wgpu::PresentMode::AutoVsync,
+        _ => wgpu::PresentMode::Mailbox,
+    }
+}
+
+// The mode after `current`, wrapping back to the first supported one
+pub fn next_present_mode(current: wgpu::PresentMode, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    let index = supported.iter().position(|mode| *mode == current).map_or(0, |index| index + 1);
+    supported.get(index).or(supported.first()).copied().unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+// Formats the surface can show that the post-processing chain can draw into. Plain 8-bit formats
+// come first since the chain's gamma effect already encodes colors for the display.
+pub fn supported_surface_formats(capabilities: &wgpu::SurfaceCapabilities) -> Vec<wgpu::TextureFormat> {
+    let mut formats: Vec<wgpu::TextureFormat> = capabilities.formats.iter().copied()
+        .filter(|format| format.sample_type(None, None) == Some(wgpu::TextureSampleType::Float { filterable: true }))
+        .collect();
+    formats.sort_by_key(|format| match format {
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Rgba8Unorm => 0,
+        format if !format.is_srgb() => 1,
+        _ => 2,
+    });
+    formats
+}
+
+// The format after `current`, wrapping back to the preferred one
+pub fn next_surface_format(current: wgpu::TextureFormat, supported: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+    let index = supported.iter().position(|format| *format == current).map_or(0, |index| index + 1);
+    supported.get(index).or(supported.first()).copied().unwrap_or(current)
+}
+
+// The cap from MUTETRA_FPS_CAP, None when unset, zero or not a number
+pub fn requested_fps_cap() -> Option<f32> {
+    std::env::var(FPS_CAP_ENV).ok()
+        .and_then(|value| value.trim().parse::<f32>().ok())
+        .filter(|fps| *fps > 0.0)
+}
+
+// Holds frames back to an optional frame rate cap. Deadlines advance by a fixed interval rather
+// than from when the wait ended, so oversleeping one frame is made up on the next.
+pub struct FramePacer {
+    interval: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new(fps_cap: Option<f32>) -> Self {
+        Self {
+            interval: fps_cap.map(|fps| Duration::from_secs_f32(1.0 / fps)),
+            next_frame: Instant::now(),
+        }
+    }
+
+    // Sleeps until the next frame is due, then spins for the last SPIN_MARGIN
+    pub fn wait(&mut self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+
+        let now = Instant::now();
+        if now >= self.next_frame {
+            // Already late, start counting from now instead of rushing to catch up
+            self.next_frame = now + interval;
+            return;
+        }
+
+        let remaining = self.next_frame - now;
+        if remaining > SPIN_MARGIN {
+            std::thread::sleep(remaining - SPIN_MARGIN);
+        }
+        while Instant::now() < self.next_frame {
+            std::hint::spin_loop();
+        }
+        self.next_frame += interval;
+    }
+}
@@ -0,0 +1,128 @@
+use crate::post::create_hdr_texture;
+
+// Set this environment variable to 1, 2, 4 or 8 to pick the starting MSAA level
+pub const MSAA_ENV: &str = "MUTETRA_MSAA";
+pub const DEFAULT_SAMPLE_COUNT: u32 = 4;
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+// The scene is lit and blended in HDR, post-processing brings it down to the window's range
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+// Request this device feature when the adapter has it. Without it wgpu validates against the formats'
+// guaranteed features, which on Vulkan, Metal and DX12 only allow 1x and 4x MSAA.
+pub const MSAA_FEATURES: wgpu::Features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+
+const SAMPLE_COUNTS: [u32; 4] = [1, 2, 4, 8];
+
+// Sample counts the device accepts for both the HDR color format and the depth format. Only with
+// MSAA_FEATURES enabled does that include everything the adapter reports.
+pub fn supported_sample_counts(adapter: &wgpu::Adapter, device: &wgpu::Device) -> Vec<u32> {
+    let format_flags = |format: wgpu::TextureFormat| {
+        if device.features().contains(MSAA_FEATURES) {
+            adapter.get_texture_format_features(format).flags
+        } else {
+            format.guaranteed_format_features(device.features()).flags
+        }
+    };
+    let color = format_flags(HDR_FORMAT);
+    let depth = format_flags(DEPTH_FORMAT);
+    SAMPLE_COUNTS.into_iter()
+        .filter(|&count| color.sample_count_supported(count) && depth.sample_count_supported(count))
+        .collect()
+}
+
+// The highest supported level not above the requested one, 1 always works
+pub fn clamp_sample_count(requested: u32, supported: &[u32]) -> u32 {
+    supported.iter().copied().filter(|&count| count <= requested).max().unwrap_or(1)
+}
+
+// The starting level from MUTETRA_MSAA, or DEFAULT_SAMPLE_COUNT when unset or not a number
+pub fn requested_sample_count() -> u32 {
+    std::env::var(MSAA_ENV).ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_SAMPLE_COUNT)
+}
+
+// The next level up from `current`, wrapping back to 1 after the highest supported one
+pub fn next_sample_count(current: u32, supported: &[u32]) -> u32 {
+    supported.iter().copied().find(|&count| count > current).unwrap_or(1)
+}
+
+// Size-dependent attachments of the main pass. The scene ends up in an HDR texture that the
+// post-processing chain reads. With MSAA it is drawn into a multisampled texture resolving into it.
+pub struct RenderTargets {
+    pub sample_count: u32,
+    color_view: Option<wgpu::TextureView>,
+    scene_view: wgpu::TextureView,
+    depth_view: wgpu::TextureView,
+}
+
+impl RenderTargets {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32, sample_count: u32) -> Self {
+        let size = wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        };
+
+        let color_view = (sample_count > 1).then(|| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("Multisampled Color Texture"),
+                size,
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format: HDR_FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                view_formats: &[],
+            }).create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let scene_view = create_hdr_texture(device, "Scene Texture", size.width, size.height);
+
+        let depth_view = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Depth Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count,
+            dimension: wgpu::TextureDimension::D2,
+            format: DEPTH_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING, // Read by SSAO
+            view_formats: &[],
+        }).create_view(&wgpu::TextureViewDescriptor::default());
+
+        Self { sample_count, color_view, scene_view, depth_view }
+    }
+
+    // Clears and draws into the scene texture, through the multisampled texture when MSAA is on
+    pub fn color_attachment(&self, clear: wgpu::Color) -> wgpu::RenderPassColorAttachment<'_> {
+        let (view, resolve_target) = match &self.color_view {
+            Some(color_view) => (color_view, Some(&self.scene_view)),
+            None => (&self.scene_view, None),
+        };
+        wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear),
+                // The multisampled samples are only needed until they are resolved
+                store: if resolve_target.is_some() { wgpu::StoreOp::Discard } else { wgpu::StoreOp::Store },
+            },
+        }
+    }
+
+    // The finished scene, input of the post-processing chain
+    pub fn scene_view(&self) -> &wgpu::TextureView {
+        &self.scene_view
+    }
+
+    pub fn depth_view(&self) -> &wgpu::TextureView {
+        &self.depth_view
+    }
+}
+
+pub fn multisample_state(sample_count: u32) -> wgpu::MultisampleState {
+    wgpu::MultisampleState {
+        count: sample_count,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+    }
+}
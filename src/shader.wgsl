@@ -2,45 +2,317 @@
 var<uniform> uniforms: Uniforms;
 
 @group(0) @binding(1)
-var texture: texture_cube<f32>; // Updated to texture_cube
+var texture: texture_2d_array<f32>; // One layer per block texture
 
 @group(0) @binding(2)
 var texture_sampler: sampler;
 
+@group(0) @binding(3)
+var<storage, read> chunks: array<ChunkDraw>;
+
+@group(0) @binding(4)
+var<storage, read> block_face_layers: array<u32>; // BlockRegistry::face_layer_table, six entries per block type
+
+@group(0) @binding(5)
+var shadow_map: texture_depth_2d_array; // One layer per cascade
+
+@group(0) @binding(6)
+var shadow_sampler: sampler_comparison;
+
+@group(0) @binding(7)
+var<storage, read> point_lights: array<PointLight>;
+
+@group(0) @binding(8)
+var<storage, read> light_clusters: array<ClusterLights>;
+
+@group(0) @binding(9)
+var<uniform> cluster: ClusterUniforms;
+
+const SHADOW_CASCADES: u32 = 3u; // shadow::SHADOW_CASCADES
+const MAX_LIGHTS_PER_CLUSTER: u32 = 64u; // point_light::MAX_LIGHTS_PER_CLUSTER
+
 struct Uniforms {
     view_proj: mat4x4<f32>,
     model: mat4x4<f32>,
+    camera_chunk: vec4<i32>,
+    camera_offset: vec4<f32>, // w holds the chunk size
+    fog_color: vec4<f32>,
+    fog_params: vec4<f32>, // start, end, density, mode (0 linear, 1 exponential)
+    sun_direction: vec4<f32>, // Towards the sun
+    sun_color: vec4<f32>,
+    ambient_color: vec4<f32>,
+    shadow_view_proj: array<mat4x4<f32>, 3>,
+    shadow_params: vec4<f32>, // texel size, normal offset
+}
+
+// See cluster.wgsl, which fills light_clusters each frame
+struct ClusterUniforms {
+    view: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    grid: vec4<u32>, // Clusters along x, y and z, light count
+    params: vec4<f32>, // Near, far, screen width, screen height
+}
+
+struct PointLight {
+    position_radius: vec4<f32>, // Camera-relative position, radius
+    color_intensity: vec4<f32>,
+}
+
+struct ClusterLights {
+    count: u32,
+    indices: array<u32, MAX_LIGHTS_PER_CLUSTER>,
+}
+
+struct ChunkDraw {
+    chunk_pos: vec4<i32>,
+    aabb_min: vec4<f32>,
+    aabb_max: vec4<f32>,
+    index_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    _padding: u32,
 }
 
 struct VertexInput {
     @location(0)
-    position: vec4<f32>,
-    @location(1)
-    tex_coords: vec2<f32>, // Keep as vec2 for input
+    data: vec2<u32>, // Packed voxel vertex, see vertex::PackedVertex
 };
 
 struct VertexOutput {
     @builtin(position)
     position: vec4<f32>,
     @location(0)
-    tex_coords: vec3<f32>, // Change to vec3
+    tex_coords: vec2<f32>,
+    @location(1)
+    ao: f32,
+    @location(2) @interpolate(flat)
+    layer: u32,
+    @location(3)
+    relative_position: vec3<f32>, // Offset from Camera::eye, used for fog
+    @location(4) @interpolate(flat)
+    normal: vec3<f32>,
+    @location(5)
+    light: vec2<f32>, // Voxel skylight and block light, 0 to 1
 };
 
+// Texture corners in the same bottom-left, bottom-right, top-right, top-left order as vertex::FACE_CORNERS.
+// Image rows start at the top, so v runs downwards.
+const CORNER_UVS = array<vec2<f32>, 4>(
+    vec2<f32>(0.0, 1.0),
+    vec2<f32>(1.0, 1.0),
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(0.0, 0.0),
+);
+
+// Brightness per AO level, 0 is a corner boxed in by two blocks and 3 is fully open
+const AO_CURVE = array<f32, 4>(0.45, 0.65, 0.85, 1.0);
+
+// Same order as vertex::FACE_NORMALS
+const FACE_NORMALS = array<vec3<f32>, 6>(
+    vec3<f32>(1.0, 0.0, 0.0),
+    vec3<f32>(-1.0, 0.0, 0.0),
+    vec3<f32>(0.0, 1.0, 0.0),
+    vec3<f32>(0.0, -1.0, 0.0),
+    vec3<f32>(0.0, 0.0, 1.0),
+    vec3<f32>(0.0, 0.0, -1.0),
+);
+
+// Face of each group of four vertex::VERTICES (front, back, top, bottom, right, left) in +X, -X, +Y, -Y, +Z, -Z numbering
+const CUBE_VERTEX_FACES = array<u32, 6>(4u, 5u, 2u, 3u, 0u, 1u);
+
+// Camera-relative position of a packed chunk vertex.
+// Chunk offsets are taken in integers, so only small camera-relative floats remain.
+fn chunk_vertex_position(data: vec2<u32>) -> vec3<f32> {
+    let local = vec3<f32>(
+        f32(data.x & 0x3Fu),
+        f32((data.x >> 6u) & 0x1FFu),
+        f32((data.x >> 15u) & 0x3Fu),
+    );
+    let slot = data.y >> 20u;
+    let chunk_delta = vec3<f32>(chunks[slot].chunk_pos.xyz - uniforms.camera_chunk.xyz);
+    return chunk_delta * uniforms.camera_offset.w + local - uniforms.camera_offset.xyz;
+}
+
 @vertex
 fn vs_main(input: VertexInput) -> VertexOutput {
     var output: VertexOutput;
+    let face = (input.data.x >> 21u) & 0x7u;
+    let corner = (input.data.x >> 24u) & 0x3u;
+    let ao = (input.data.x >> 26u) & 0x3u;
+
+    let relative = chunk_vertex_position(input.data);
+    let position = vec4<f32>(relative, 1.0);
+    let mvp = uniforms.view_proj * uniforms.model;
+    output.position = mvp * position;
+    output.relative_position = relative;
+
+    output.tex_coords = CORNER_UVS[corner];
+    output.normal = FACE_NORMALS[face];
+    output.ao = AO_CURVE[ao];
+    output.layer = input.data.y & 0xFFFu;
+    output.light = vec2<f32>(f32((input.data.y >> 16u) & 0xFu), f32((input.data.y >> 12u) & 0xFu)) / 15.0;
+    return output;
+}
+
+struct ShadowCascade {
+    index: u32,
+}
+
+@group(1) @binding(0)
+var<uniform> shadow_cascade: ShadowCascade;
+
+// Depth-only pass from the sun, see shadow::ShadowMaps
+@vertex
+fn vs_shadow(input: VertexInput) -> @builtin(position) vec4<f32> {
+    let relative = chunk_vertex_position(input.data);
+    return uniforms.shadow_view_proj[shadow_cascade.index] * vec4<f32>(relative, 1.0);
+}
+
+struct CubeVertexInput {
+    @location(0)
+    position: vec3<f32>,
+    @location(1)
+    tex_coords: vec2<f32>,
+};
+
+struct InstanceInput {
+    @location(2)
+    offset: vec3<f32>, // Camera-relative block corner
+    @location(3)
+    block_type: u32,
+};
+
+@vertex
+fn vs_instanced(@builtin(vertex_index) vertex_index: u32, vertex: CubeVertexInput, instance: InstanceInput) -> VertexOutput {
+    var output: VertexOutput;
+
+    // vertex::VERTICES spans -1..1, shrink it to one block starting at the instance corner
+    let local = vertex.position * 0.5 + vec3<f32>(0.5);
     let mvp = uniforms.view_proj * uniforms.model;
-    output.position = mvp * input.position;
-    
-    // Convert 2D texture coordinates to 3D direction for cubemap sampling
-    output.tex_coords = vec3<f32>(input.tex_coords, 1.0); // Example conversion, adjust as needed
+    output.position = mvp * vec4<f32>(instance.offset + local, 1.0);
+    output.relative_position = instance.offset + local;
+
+    output.tex_coords = vec2<f32>(vertex.tex_coords.x, 1.0 - vertex.tex_coords.y);
+    output.ao = 1.0;
+    let face = CUBE_VERTEX_FACES[vertex_index / 4u];
+    output.layer = block_face_layers[instance.block_type * 6u + face];
+    output.normal = FACE_NORMALS[face];
+    output.light = vec2<f32>(1.0, 0.0); // Instances sit in the open
     return output;
 }
 
+// Torches and lava glow warmer than daylight
+const BLOCK_LIGHT_COLOR = vec3<f32>(1.0, 0.85, 0.6);
+
+// Each light level is 80% as bright as the one above it, like the classic voxel light curve
+fn light_curve(level: f32) -> f32 {
+    return pow(0.8, (1.0 - level) * 15.0);
+}
+
+// 0 at the camera, 1 where terrain is fully hidden in fog
+fn fog_factor(distance: f32) -> f32 {
+    let start = uniforms.fog_params.x;
+    let end = uniforms.fog_params.y;
+    if distance >= end {
+        return 1.0; // Hides chunks loading in at the edge of the view distance
+    }
+    if uniforms.fog_params.w > 0.5 {
+        return 1.0 - exp(-uniforms.fog_params.z * distance);
+    }
+    return clamp((distance - start) / (end - start), 0.0, 1.0);
+}
+
+// 3x3 PCF in the first cascade that contains the point, 1 where the sun is visible
+fn shadow_factor(relative_position: vec3<f32>, normal: vec3<f32>) -> f32 {
+    // Pushing the lookup out along the normal avoids acne on surfaces facing away from the sun
+    let position = vec4<f32>(relative_position + normal * uniforms.shadow_params.y, 1.0);
+    let texel = uniforms.shadow_params.x;
+    for (var cascade = 0u; cascade < SHADOW_CASCADES; cascade++) {
+        let light = uniforms.shadow_view_proj[cascade] * position;
+        let ndc = light.xyz / light.w;
+        let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        if any(uv < vec2<f32>(texel)) || any(uv > vec2<f32>(1.0 - texel)) || ndc.z < 0.0 || ndc.z > 1.0 {
+            continue;
+        }
+
+        var lit = 0.0;
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let offset = vec2<f32>(f32(x), f32(y)) * texel;
+                lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, cascade, ndc.z);
+            }
+        }
+        return lit / 9.0;
+    }
+    return 1.0; // Beyond the last cascade, fog covers everything anyway
+}
+
+// Diffuse light from the point lights binned into this pixel's cluster
+fn point_lighting(input: VertexOutput) -> vec3<f32> {
+    let view_depth = -(cluster.view * vec4<f32>(input.relative_position, 1.0)).z;
+    let near = cluster.params.x;
+    let far = cluster.params.y;
+    let grid = cluster.grid.xyz;
+    let tile = min(vec2<u32>(input.position.xy / cluster.params.zw * vec2<f32>(grid.xy)), grid.xy - 1u);
+    let slice = u32(clamp(log(max(view_depth, near) / near) / log(far / near) * f32(grid.z), 0.0, f32(grid.z - 1u)));
+    let index = (slice * grid.y + tile.y) * grid.x + tile.x;
+
+    var lighting = vec3<f32>(0.0);
+    let count = light_clusters[index].count;
+    for (var i = 0u; i < count; i++) {
+        let light = point_lights[light_clusters[index].indices[i]];
+        let to_light = light.position_radius.xyz - input.relative_position;
+        let distance = length(to_light);
+        // Inverse square falloff windowed to reach exactly zero at the radius
+        let window = clamp(1.0 - pow(distance / light.position_radius.w, 4.0), 0.0, 1.0);
+        let attenuation = window * window / (distance * distance + 1.0);
+        let diffuse = max(dot(input.normal, to_light / max(distance, 0.0001)), 0.0);
+        lighting += light.color_intensity.rgb * light.color_intensity.w * attenuation * diffuse;
+    }
+    return lighting;
+}
+
+// Lit, shadowed and fogged color of a sampled texel
+fn shade(input: VertexOutput, sampled_color: vec4<f32>) -> vec4<f32> {
+    let diffuse = max(dot(input.normal, uniforms.sun_direction.xyz), 0.0) * shadow_factor(input.relative_position, input.normal);
+    // Skylight scales the sun and ambient light, so caves stay dark at noon, block light and point lights are added on top
+    let sky = light_curve(input.light.x);
+    let light = (uniforms.ambient_color.rgb + uniforms.sun_color.rgb * diffuse) * sky
+        + BLOCK_LIGHT_COLOR * light_curve(input.light.y) * step(0.001, input.light.y)
+        + point_lighting(input);
+    let fog = fog_factor(length(input.relative_position));
+    let color = mix(sampled_color.rgb * light * input.ao, uniforms.fog_color.rgb, fog);
+    return vec4<f32>(color, sampled_color.a);
+}
+
+// The sampler is trilinear and anisotropic. Where texels are larger than a pixel the coordinates are
+// snapped to texel centers, so close-up faces keep crisp nearest-filtered texels. The unsnapped
+// gradients still pick the mip level.
+fn sample_block(input: VertexOutput) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(texture));
+    let ddx = dpdx(input.tex_coords);
+    let ddy = dpdy(input.tex_coords);
+    let texels_per_pixel = max(length(ddx * size), length(ddy * size));
+    let snapped = (floor(input.tex_coords * size) + 0.5) / size;
+    let coords = select(input.tex_coords, snapped, texels_per_pixel <= 1.0);
+    return textureSampleGrad(texture, texture_sampler, coords, input.layer, ddx, ddy);
+}
+
+// Cutout textures (leaves, glass panes) are either fully solid or fully see-through
+const CUTOUT_ALPHA_THRESHOLD: f32 = 0.5;
+
 @fragment
 fn fs_main(input: VertexOutput) -> @location(0) vec4<f32> {
-    let sampled_color = textureSample(texture, texture_sampler, input.tex_coords); // Use vec3 for cubemap
-    
-    // Output the sampled color
-    return vec4<f32>(sampled_color.rgb, sampled_color.a); // Include alpha handling
+    let sampled_color = sample_block(input);
+    if (sampled_color.a < CUTOUT_ALPHA_THRESHOLD) {
+        discard;
+    }
+    return vec4<f32>(shade(input, sampled_color).rgb, 1.0);
+}
+
+// Translucent blocks (water, stained glass) keep their alpha and are blended over the opaque scene
+@fragment
+fn fs_translucent(input: VertexOutput) -> @location(0) vec4<f32> {
+    let sampled_color = sample_block(input);
+    return shade(input, sampled_color);
 }
@@ -0,0 +1,312 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+use wgpu::util::DeviceExt;
+use crate::camera::Camera;
+use crate::chunk::CHUNK_HEIGHT;
+use crate::indirect::ChunkArena;
+use crate::vertex::PackedVertex;
+
+pub const SHADOW_CASCADES: usize = 3;
+pub const SHADOW_MAP_SIZE: u32 = 2048;
+pub const SHADOW_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+// Blend between logarithmic (1.0) and uniform (0.0) cascade splits
+const SPLIT_LAMBDA: f32 = 0.6;
+// Room behind each cascade for casters outside the camera frustum, such as a wall behind the camera
+const CASTER_MARGIN: f32 = CHUNK_HEIGHT as f32 * 2.0;
+// Dynamic uniform offsets must be aligned to this
+const CASCADE_STRIDE: u64 = 256;
+
+// cgmath builds OpenGL clip space with z in -1..1, wgpu expects 0..1
+const OPENGL_TO_WGPU_MATRIX: Matrix4<f32> = Matrix4::new(
+    1.0, 0.0, 0.0, 0.0,
+    0.0, 1.0, 0.0, 0.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
+);
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct CascadeIndex {
+    index: u32,
+    _padding: [u32; 3],
+}
+
+// Fits one orthographic sun projection around each slice of the camera frustum, all camera-relative
+pub fn cascade_matrices(camera: &Camera, sun_direction: Vector3<f32>, max_distance: f32) -> [[[f32; 4]; 4]; SHADOW_CASCADES] {
+    let near = camera.znear;
+    let far = camera.zfar.min(max_distance).max(near + 1.0);
+
+    let mut splits = [0.0; SHADOW_CASCADES + 1];
+    splits[0] = near;
+    for (i, split) in splits.iter_mut().enumerate().skip(1) {
+        let t = i as f32 / SHADOW_CASCADES as f32;
+        let logarithmic = near * (far / near).powf(t);
+        let uniform = near + (far - near) * t;
+        *split = SPLIT_LAMBDA * logarithmic + (1.0 - SPLIT_LAMBDA) * uniform;
+    }
+
+    let forward = (camera.target - camera.eye).normalize();
+    let right = forward.cross(camera.up).normalize();
+    let up = right.cross(forward);
+    let tan_half_fovy = (camera.fovy / 2.0).tan();
+
+    let sun = sun_direction.normalize();
+    // look_to_rh breaks down when the sun is straight overhead
+    let light_up = if sun.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+
+    let mut matrices = [[[0.0; 4]; 4]; SHADOW_CASCADES];
+    for (cascade, matrix) in matrices.iter_mut().enumerate() {
+        let mut corners = Vec::with_capacity(8);
+        for distance in [splits[cascade], splits[cascade + 1]] {
+            let half_height = distance * tan_half_fovy;
+            let half_width = half_height * camera.aspect;
+            for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                corners.push(forward * distance + right * (half_width * x) + up * (half_height * y));
+            }
+        }
+
+        // A bounding sphere keeps the cascade size constant while the camera turns
+        let center = corners.iter().fold(Vector3::new(0.0, 0.0, 0.0), |sum, corner| sum + corner) / corners.len() as f32;
+        let radius = corners.iter().map(|corner| (corner - center).magnitude()).fold(0.0f32, f32::max).ceil();
+
+        let eye = Point3::new(center.x, center.y, center.z) + sun * (radius + CASTER_MARGIN);
+        let view = Matrix4::look_to_rh(eye, -sun, light_up);
+        let projection = cgmath::ortho(-radius, radius, -radius, radius, 0.0, 2.0 * radius + CASTER_MARGIN);
+        *matrix = (OPENGL_TO_WGPU_MATRIX * projection * view).into();
+    }
+    matrices
+}
+
+// Depth-only passes from the sun into a texture array with one layer per cascade
+pub struct ShadowMaps {
+    pub pipeline: wgpu::RenderPipeline,
+    pub pipeline_layout: wgpu::PipelineLayout,
+    array_view: wgpu::TextureView,
+    cascade_views: Vec<wgpu::TextureView>,
+    sampler: wgpu::Sampler,
+    bind_group_layout: wgpu::BindGroupLayout,
+    cascade_bind_group: wgpu::BindGroup,
+}
+
+impl ShadowMaps {
+    pub fn new(device: &wgpu::Device, shader: &wgpu::ShaderModule) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Shadow Map Texture"),
+            size: wgpu::Extent3d {
+                width: SHADOW_MAP_SIZE,
+                height: SHADOW_MAP_SIZE,
+                depth_or_array_layers: SHADOW_CASCADES as u32,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: SHADOW_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let cascade_views = (0..SHADOW_CASCADES as u32)
+            .map(|layer| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("Shadow Cascade View"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            }))
+            .collect();
+
+        // Linear comparison filtering gives a smooth 2x2 PCF per tap on top of the shader's kernel
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+
+        // The shadow pass can't use the main bind group, which samples the shadow map it renders into
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("shadow_bind_group_layout"),
+        });
+
+        let cascade_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<CascadeIndex>() as u64),
+                },
+                count: None,
+            }],
+            label: Some("shadow_cascade_bind_group_layout"),
+        });
+
+        // One aligned entry per cascade, picked with a dynamic offset in each shadow pass
+        let mut cascade_data = vec![0u8; CASCADE_STRIDE as usize * SHADOW_CASCADES];
+        for cascade in 0..SHADOW_CASCADES {
+            let index = CascadeIndex { index: cascade as u32, _padding: [0; 3] };
+            let offset = cascade * CASCADE_STRIDE as usize;
+            cascade_data[offset..offset + std::mem::size_of::<CascadeIndex>()].copy_from_slice(bytemuck::bytes_of(&index));
+        }
+        let cascade_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shadow Cascade Buffer"),
+            contents: &cascade_data,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let cascade_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &cascade_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &cascade_buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(std::mem::size_of::<CascadeIndex>() as u64),
+                }),
+            }],
+            label: Some("shadow_cascade_bind_group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout, &cascade_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = create_shadow_pipeline(device, &pipeline_layout, shader);
+
+        Self {
+            pipeline,
+            pipeline_layout,
+            array_view,
+            cascade_views,
+            sampler,
+            bind_group_layout,
+            cascade_bind_group,
+        }
+    }
+
+    // Uniforms and chunk slots for the shadow pass, rebuilt by the caller like the main bind group
+    pub fn create_bind_group(&self, device: &wgpu::Device, uniform_buffer: &wgpu::Buffer, chunk_arena: &ChunkArena) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: chunk_arena.chunk_binding(),
+                },
+            ],
+            label: Some("shadow_bind_group"),
+        })
+    }
+
+    pub fn texture_binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::TextureView(&self.array_view)
+    }
+
+    pub fn sampler_binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Sampler(&self.sampler)
+    }
+
+    // Records one depth pass per cascade. Every loaded chunk is drawn, since casters can sit outside the camera frustum.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, bind_group: &wgpu::BindGroup, chunk_arena: &ChunkArena) {
+        for (cascade, view) in self.cascade_views.iter().enumerate() {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+
+            shadow_pass.set_pipeline(&self.pipeline);
+            shadow_pass.set_bind_group(0, bind_group, &[]);
+            shadow_pass.set_bind_group(1, &self.cascade_bind_group, &[(cascade as u64 * CASCADE_STRIDE) as u32]);
+            chunk_arena.draw_all(&mut shadow_pass);
+        }
+    }
+}
+
+pub fn create_shadow_pipeline(device: &wgpu::Device, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Shadow Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_shadow"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<PackedVertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Uint32x2],
+            }],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: None,
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None, // Thin terrain would leak light if back faces were dropped
+            ..Default::default()
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: SHADOW_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            // Slope-scaled bias keeps surfaces from shadowing themselves
+            bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+        }),
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+        cache: None,
+    })
+}
@@ -0,0 +1,241 @@
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+use crate::camera::Camera;
+use crate::day_night::SkyColors;
+use crate::fog::FogSettings;
+use crate::render_targets::{multisample_state, DEPTH_FORMAT};
+use crate::texture::Texture;
+use crate::vertex::{Vertex, VERTICES, INDICES};
+
+#[derive(Deserialize)]
+struct SkyTextures {
+    pos_x: String,
+    neg_x: String,
+    pos_y: String,
+    neg_y: String,
+    pos_z: String,
+    neg_z: String,
+}
+
+#[derive(Deserialize)]
+struct SkyFile {
+    textures: SkyTextures,
+    #[serde(default)]
+    fog: FogSettings,
+}
+
+pub struct SkyConfig {
+    pub textures: [String; 6], // Cubemap faces in +X, -X, +Y, -Y, +Z, -Z order
+    pub fog: FogSettings,
+}
+
+impl SkyConfig {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read sky config {}: {}", path, e))?;
+        let file: SkyFile = toml::from_str(&source)
+            .map_err(|e| format!("Failed to load sky config {}: {}", path, e))?;
+        let t = file.textures;
+        Ok(Self {
+            textures: [t.pos_x, t.neg_x, t.pos_y, t.neg_y, t.pos_z, t.neg_z],
+            fog: file.fog,
+        })
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct SkyUniforms {
+    view_proj: [[f32; 4]; 4],
+    zenith_color: [f32; 4],
+    horizon_color: [f32; 4],
+}
+
+pub struct Sky {
+    pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    bind_group: wgpu::BindGroup,
+    uniform_buffer: wgpu::Buffer,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    _texture: Texture,
+}
+
+impl Sky {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, config: &SkyConfig, format: wgpu::TextureFormat, sample_count: u32) -> Self {
+        let [pos_x, neg_x, pos_y, neg_y, pos_z, neg_z] = &config.textures;
+        // Loaded per Sky rather than shared, since each renderer has its own device and sky.toml may change
+        let texture = Texture::from_images(device, queue, [pos_x, neg_x, pos_y, neg_y, pos_z, neg_z]);
+
+        // The ±1 cube from vertex::VERTICES, only its positions are used as sample directions
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sky Index Buffer"),
+            contents: bytemuck::cast_slice(INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Sky Uniform Buffer"),
+            size: std::mem::size_of::<SkyUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("sky_bind_group_layout"),
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&texture.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
+                },
+            ],
+            label: Some("sky_bind_group"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Sky Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("sky.wgsl").into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Sky Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = create_sky_pipeline(device, &pipeline_layout, &shader, format, sample_count);
+
+        Self {
+            pipeline,
+            pipeline_layout,
+            shader,
+            bind_group,
+            uniform_buffer,
+            vertex_buffer,
+            index_buffer,
+            _texture: texture,
+        }
+    }
+
+    // Rebuilds the pipeline for a new MSAA level, it has to match the render pass attachments
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, format: wgpu::TextureFormat, sample_count: u32) {
+        self.pipeline = create_sky_pipeline(device, &self.pipeline_layout, &self.shader, format, sample_count);
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera, colors: &SkyColors) {
+        let uniforms = SkyUniforms {
+            view_proj: camera.build_sky_view_projection_matrix().into(),
+            zenith_color: [colors.zenith[0], colors.zenith[1], colors.zenith[2], 1.0],
+            horizon_color: [colors.horizon[0], colors.horizon[1], colors.horizon[2], 1.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+
+    // Call first in the render pass, before any terrain
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.draw_indexed(0..INDICES.len() as u32, 0, 0..1);
+    }
+}
+
+fn create_sky_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Sky Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: Some("vs_sky"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+            }],
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: Some("fs_sky"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None, // The camera sits inside the cube
+            ..Default::default()
+        },
+        // Depth is tested against the cleared far plane but never written, so terrain always lands on top
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            depth_write_enabled: false,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: multisample_state(sample_count),
+        multiview: None,
+        cache: None,
+    })
+}
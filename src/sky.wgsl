@@ -0,0 +1,42 @@
+@group(0) @binding(0)
+var<uniform> sky: SkyUniforms;
+
+@group(0) @binding(1)
+var sky_texture: texture_cube<f32>;
+
+@group(0) @binding(2)
+var sky_sampler: sampler;
+
+struct SkyUniforms {
+    view_proj: mat4x4<f32>, // Rotation-only view, the sky never moves with the camera
+    zenith_color: vec4<f32>,
+    horizon_color: vec4<f32>, // Also the fog color, so terrain fades into the sky
+}
+
+struct VertexOutput {
+    @builtin(position)
+    position: vec4<f32>,
+    @location(0)
+    direction: vec3<f32>,
+};
+
+@vertex
+fn vs_sky(@location(0) position: vec3<f32>) -> VertexOutput {
+    var output: VertexOutput;
+    let clip = sky.view_proj * vec4<f32>(position, 1.0);
+    // z = w puts every sky fragment exactly on the far plane, behind all terrain
+    output.position = clip.xyww;
+    output.direction = position;
+    return output;
+}
+
+@fragment
+fn fs_sky(input: VertexOutput) -> @location(0) vec4<f32> {
+    // Gradient from the horizon up to the zenith, everything below the horizon matches the fog
+    let height = clamp(normalize(input.direction).y, 0.0, 1.0);
+    let gradient = mix(sky.horizon_color.rgb, sky.zenith_color.rgb, sqrt(height));
+
+    // The cubemap adds detail such as clouds or stars on top, a white cubemap shows the plain gradient
+    let detail = textureSample(sky_texture, sky_sampler, input.direction);
+    return vec4<f32>(gradient * detail.rgb, 1.0);
+}
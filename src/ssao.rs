@@ -0,0 +1,290 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{Matrix4, SquareMatrix};
+use serde::Deserialize;
+use crate::camera::Camera;
+use crate::post::{FullscreenPass, create_hdr_texture};
+use crate::render_targets::{HDR_FORMAT, RenderTargets};
+
+const KERNEL_SIZE: usize = 16; // Must match SSAO_SAMPLES in ssao.wgsl
+const AO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+// The [ssao] table of src/post.toml
+#[derive(Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    pub radius: f32, // World units around each pixel that can occlude it
+    pub strength: f32, // Exponent applied to the AO term, above 1 darkens creases further
+    pub bias: f32, // Keeps flat surfaces from occluding themselves
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            radius: 0.5,
+            strength: 1.5,
+            bias: 0.025,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct SsaoUniforms {
+    projection: [[f32; 4]; 4],
+    inverse_projection: [[f32; 4]; 4],
+    kernel: [[f32; 4]; KERNEL_SIZE],
+    params: [f32; 4],
+}
+
+// Sample offsets spread over the +Z hemisphere along a golden-angle spiral, scaled so more of
+// them land close to the center where occlusion matters most
+fn hemisphere_kernel() -> [[f32; 4]; KERNEL_SIZE] {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5.0f32.sqrt());
+    let mut kernel = [[0.0; 4]; KERNEL_SIZE];
+    for (i, sample) in kernel.iter_mut().enumerate() {
+        let t = (i as f32 + 0.5) / KERNEL_SIZE as f32;
+        let z = 1.0 - t; // Never flat, every sample points at least slightly out of the surface
+        let ring = (1.0 - z * z).sqrt();
+        let angle = golden_angle * i as f32;
+        let scale = 0.1 + 0.9 * t * t;
+        *sample = [ring * angle.cos() * scale, ring * angle.sin() * scale, z * scale, 0.0];
+    }
+    kernel
+}
+
+// Reconstructs view-space positions from the depth buffer, darkens the creases between them and
+// multiplies the blurred result into the HDR scene before post-processing
+pub struct Ssao {
+    pub settings: SsaoSettings,
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    uniform_buffer: wgpu::Buffer,
+    blur: FullscreenPass,
+    composite: FullscreenPass,
+    ao_views: [wgpu::TextureView; 2], // Raw and blurred
+    output_view: wgpu::TextureView,
+    // Read the render targets and the AO textures, so they are rebuilt whenever either is recreated
+    bind_group: wgpu::BindGroup,
+    blur_bind_group: wgpu::BindGroup,
+    composite_bind_group: wgpu::BindGroup,
+    sample_count: u32,
+}
+
+impl Ssao {
+    pub fn new(device: &wgpu::Device, settings: SsaoSettings, render_targets: &RenderTargets, width: u32, height: u32) -> Self {
+        let sample_count = render_targets.sample_count;
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("SSAO Uniform Buffer"),
+            size: std::mem::size_of::<SsaoUniforms>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let source = include_str!("ssao_blur.wgsl");
+        let blur = FullscreenPass::new(device, "SSAO Blur Pass", source, "fs_blur", None, AO_FORMAT);
+        let composite = FullscreenPass::new(device, "SSAO Composite Pass", source, "fs_composite", Some(wgpu::TextureViewDimension::D2), HDR_FORMAT);
+        let (bind_group_layout, pipeline) = Self::create_pipeline(device, sample_count);
+        let (ao_views, output_view) = Self::create_targets(device, width, height);
+        let bind_group = Self::create_bind_group(device, &bind_group_layout, &uniform_buffer, render_targets);
+        let blur_bind_group = blur.create_bind_group(device, &ao_views[0], None);
+        let composite_bind_group = composite.create_bind_group(device, render_targets.scene_view(), Some(&ao_views[1]));
+
+        Self {
+            settings,
+            pipeline,
+            bind_group_layout,
+            uniform_buffer,
+            blur,
+            composite,
+            ao_views,
+            output_view,
+            bind_group,
+            blur_bind_group,
+            composite_bind_group,
+            sample_count,
+        }
+    }
+
+    // The depth binding is a different texture type with MSAA, so the shader is rebuilt for it.
+    // Depth is bound as unfilterable float, since GL can't load from depth textures.
+    fn create_pipeline(device: &wgpu::Device, sample_count: u32) -> (wgpu::BindGroupLayout, wgpu::RenderPipeline) {
+        let multisampled = sample_count > 1;
+        let depth_type = if multisampled { "texture_multisampled_2d<f32>" } else { "texture_2d<f32>" };
+        let source = format!(
+            "@group(0) @binding(0)\nvar depth_texture: {};\n\nfn load_depth(coord: vec2<i32>) -> f32 {{\n    return textureLoad(depth_texture, coord, 0).r;\n}}\n\n{}",
+            depth_type,
+            include_str!("ssao.wgsl"),
+        );
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("ssao_bind_group_layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("SSAO Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("SSAO Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("SSAO Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_ssao"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some("fs_ssao"),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: AO_FORMAT,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+
+        (bind_group_layout, pipeline)
+    }
+
+    fn create_targets(device: &wgpu::Device, width: u32, height: u32) -> ([wgpu::TextureView; 2], wgpu::TextureView) {
+        let ao_view = |label: &str| device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: AO_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        }).create_view(&wgpu::TextureViewDescriptor::default());
+
+        (
+            [ao_view("SSAO Texture"), ao_view("SSAO Blurred Texture")],
+            create_hdr_texture(device, "SSAO Output Texture", width, height),
+        )
+    }
+
+    fn create_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, uniform_buffer: &wgpu::Buffer, render_targets: &RenderTargets) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(render_targets.depth_view()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("ssao_bind_group"),
+        })
+    }
+
+    fn rebuild_bind_groups(&mut self, device: &wgpu::Device, render_targets: &RenderTargets) {
+        self.bind_group = Self::create_bind_group(device, &self.bind_group_layout, &self.uniform_buffer, render_targets);
+        self.blur_bind_group = self.blur.create_bind_group(device, &self.ao_views[0], None);
+        self.composite_bind_group = self.composite.create_bind_group(device, render_targets.scene_view(), Some(&self.ao_views[1]));
+    }
+
+    // Called after the render targets are recreated at the new size
+    pub fn resize(&mut self, device: &wgpu::Device, render_targets: &RenderTargets, width: u32, height: u32) {
+        (self.ao_views, self.output_view) = Self::create_targets(device, width, height);
+        self.rebuild_bind_groups(device, render_targets);
+    }
+
+    // Called after the render targets are recreated with a new sample count
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, render_targets: &RenderTargets) {
+        if render_targets.sample_count != self.sample_count {
+            (self.bind_group_layout, self.pipeline) = Self::create_pipeline(device, render_targets.sample_count);
+            self.sample_count = render_targets.sample_count;
+        }
+        self.rebuild_bind_groups(device, render_targets);
+    }
+
+    pub fn update(&self, queue: &wgpu::Queue, camera: &Camera) {
+        let projection = camera.build_projection_matrix();
+        let inverse_projection = projection.invert().unwrap_or(Matrix4::identity());
+        let uniforms = SsaoUniforms {
+            projection: projection.into(),
+            inverse_projection: inverse_projection.into(),
+            kernel: hemisphere_kernel(),
+            params: [self.settings.radius, self.settings.bias, self.settings.strength, 0.0],
+        };
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[uniforms]));
+    }
+
+    // Records the SSAO passes and returns the texture the post-processing chain should read,
+    // which is the untouched scene while SSAO is turned off
+    pub fn render<'a>(&'a self, encoder: &mut wgpu::CommandEncoder, render_targets: &'a RenderTargets) -> &'a wgpu::TextureView {
+        if !self.settings.enabled {
+            return render_targets.scene_view();
+        }
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("SSAO Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &self.ao_views[0],
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+
+        self.blur.draw_with(encoder, &self.blur_bind_group, &self.ao_views[1]);
+        self.composite.draw_with(encoder, &self.composite_bind_group, &self.output_view);
+        &self.output_view
+    }
+}
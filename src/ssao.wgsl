@@ -0,0 +1,84 @@
+// Screen-space ambient occlusion from the depth buffer. ssao.rs prepends the depth binding,
+// `depth_texture` is either texture_2d<f32> or texture_multisampled_2d<f32> depending on MSAA. It is
+// bound as unfilterable float rather than as a depth texture, since GLSL can't textureLoad depth.
+
+const SSAO_SAMPLES: u32 = 16u; // ssao::KERNEL_SIZE
+
+struct SsaoUniforms {
+    projection: mat4x4<f32>,
+    inverse_projection: mat4x4<f32>,
+    kernel: array<vec4<f32>, SSAO_SAMPLES>, // Hemisphere around +Z, denser near the center
+    params: vec4<f32>, // Radius, depth bias, strength, unused
+}
+
+@group(0) @binding(1)
+var<uniform> ssao: SsaoUniforms;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_ssao(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+fn view_position(coord: vec2<i32>, size: vec2<f32>) -> vec3<f32> {
+    let uv = (vec2<f32>(coord) + 0.5) / size;
+    let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, load_depth(coord), 1.0);
+    let view = ssao.inverse_projection * ndc;
+    return view.xyz / view.w;
+}
+
+// A 4x4 ordered pattern of kernel rotations, removed again by the 4x4 blur that follows
+const ROTATIONS = array<f32, 16>(0.0, 8.0, 2.0, 10.0, 12.0, 4.0, 14.0, 6.0, 3.0, 11.0, 1.0, 9.0, 15.0, 7.0, 13.0, 5.0);
+
+@fragment
+fn fs_ssao(input: VertexOutput) -> @location(0) vec4<f32> {
+    let dimensions = vec2<i32>(textureDimensions(depth_texture));
+    let size = vec2<f32>(dimensions);
+    let coord = vec2<i32>(input.position.xy);
+    if (load_depth(coord) >= 1.0) {
+        return vec4<f32>(1.0); // Sky
+    }
+
+    // The normal comes from whichever neighbours are closest in depth, so edges don't smear it
+    let center = view_position(coord, size);
+    let right = view_position(min(coord + vec2<i32>(1, 0), dimensions - 1), size) - center;
+    let left = center - view_position(max(coord - vec2<i32>(1, 0), vec2<i32>(0)), size);
+    let down = view_position(min(coord + vec2<i32>(0, 1), dimensions - 1), size) - center;
+    let up = center - view_position(max(coord - vec2<i32>(0, 1), vec2<i32>(0)), size);
+    let dx = select(left, right, abs(right.z) < abs(left.z));
+    let dy = select(up, down, abs(down.z) < abs(up.z));
+    let normal = normalize(cross(dy, dx));
+
+    let angle = ROTATIONS[(coord.y % 4) * 4 + coord.x % 4] / 16.0 * 6.2831853;
+    let random = vec3<f32>(cos(angle), sin(angle), 0.0);
+    let tangent = normalize(random - normal * dot(random, normal));
+    let tbn = mat3x3<f32>(tangent, cross(normal, tangent), normal);
+
+    let radius = ssao.params.x;
+    let bias = ssao.params.y;
+    var occlusion = 0.0;
+    for (var i = 0u; i < SSAO_SAMPLES; i++) {
+        let sample_position = center + tbn * ssao.kernel[i].xyz * radius;
+        let clip = ssao.projection * vec4<f32>(sample_position, 1.0);
+        let ndc = clip.xy / clip.w;
+        let sample_uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        let sample_coord = clamp(vec2<i32>(sample_uv * size), vec2<i32>(0), dimensions - 1);
+        let scene_depth = view_position(sample_coord, size).z;
+
+        // The camera looks down -Z, so geometry in front of the sample has a larger z.
+        // Occluders far outside the radius (a wall behind a ledge) fade out instead of darkening.
+        let range = smoothstep(0.0, 1.0, radius / abs(center.z - scene_depth));
+        occlusion += select(0.0, 1.0, scene_depth >= sample_position.z + bias) * range;
+    }
+
+    let ao = pow(1.0 - occlusion / f32(SSAO_SAMPLES), ssao.params.z);
+    return vec4<f32>(ao, ao, ao, 1.0);
+}
@@ -0,0 +1,26 @@
+// fs_blur averages the 4x4 block around each pixel, exactly covering one tile of the SSAO rotation
+// pattern. fs_composite multiplies the blurred AO, bound at binding 3, into the scene.
+
+@group(0) @binding(3)
+var ao_texture: texture_2d<f32>;
+
+@fragment
+fn fs_blur(input: VertexOutput) -> @location(0) vec4<f32> {
+    let dimensions = vec2<i32>(textureDimensions(input_texture));
+    let coord = vec2<i32>(input.position.xy);
+    var ao = 0.0;
+    for (var y = -2; y < 2; y++) {
+        for (var x = -2; x < 2; x++) {
+            ao += textureLoad(input_texture, clamp(coord + vec2<i32>(x, y), vec2<i32>(0), dimensions - 1), 0).r;
+        }
+    }
+    ao /= 16.0;
+    return vec4<f32>(ao, ao, ao, 1.0);
+}
+
+@fragment
+fn fs_composite(input: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(input_texture, input_sampler, input.uv);
+    let ao = textureSample(ao_texture, input_sampler, input.uv).r;
+    return vec4<f32>(color.rgb * ao, color.a);
+}
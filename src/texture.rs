@@ -1,5 +1,3 @@
-use std::sync::{Arc, Mutex};
-
 #[allow(dead_code)]
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -31,7 +29,7 @@ impl Texture {
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: Some("Cubemap Texture"),
             size: texture_size,
-            mip_level_count: 1,
+            mip_level_count: mip_level_count(texture_size.width, texture_size.height),
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -41,63 +39,209 @@ impl Texture {
 
         for (i, rgba) in rgba_images.iter().enumerate() {
             println!("Writing texture for image {}: width = {}, height = {}", i, rgba.width(), rgba.height());
-            queue.write_texture(
-                wgpu::ImageCopyTexture {
-                    texture: &texture,
-                    mip_level: 0,
-                    origin: wgpu::Origin3d {
-                        x: 0,
-                        y: 0,
-                        z: i as u32, // Set Z to the index of the image
-                    },
-                    aspect: wgpu::TextureAspect::All,
-                },
-                rgba,
-                wgpu::ImageDataLayout {
-                    offset: 0,
-                    bytes_per_row: Some(4 * rgba.width()), // Change to Option<u32>
-                    rows_per_image: Some(rgba.height()), // Change to Option<u32>
-                },
-                wgpu::Extent3d {
-                    width: rgba.width(),
-                    height: rgba.height(),
-                    depth_or_array_layers: 1, // Set depth to 1 for each image
-                },
-            );
+            write_mip_chain(queue, &texture, i as u32, rgba, MipFilter::Box);
         }
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor {
             dimension: Some(wgpu::TextureViewDimension::Cube),
             ..Default::default()
         });
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::MirrorRepeat,
-            address_mode_v: wgpu::AddressMode::MirrorRepeat,
-            address_mode_w: wgpu::AddressMode::MirrorRepeat,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            lod_min_clamp: 0.0,
-            lod_max_clamp: 100.0,
-            compare: None,
-            anisotropy_clamp: 16, // Change to u16
-            border_color: None,
-            label: Some("Texture Sampler"),
-        });
+        let sampler = create_sampler(device, false);
 
         Self { texture, view, sampler }
     }
+
+    // One layer per image, sampled as a 2D texture array so each block face can pick its own layer.
+    // Layers flagged in `cutout` keep their alpha-tested coverage through the mip chain.
+    pub fn from_layers(device: &wgpu::Device, queue: &wgpu::Queue, paths: &[&str], cutout: &[bool]) -> Self {
+        Self::try_from_layers(device, queue, paths, cutout).expect("Failed to open texture image: Check file path and existence")
+    }
+
+    // Like `from_layers`, but reports unreadable images instead of panicking (used by hot reloading)
+    pub fn try_from_layers(device: &wgpu::Device, queue: &wgpu::Queue, paths: &[&str], cutout: &[bool]) -> Result<Self, String> {
+        let mut rgba_images = Vec::new();
+        for path in paths.iter() {
+            let img = image::open(path).map_err(|e| format!("Failed to open texture image {}: {}", path, e))?;
+            rgba_images.push(img.to_rgba8());
+        }
+
+        // Every layer has to share the size of the first image
+        let (width, height) = rgba_images[0].dimensions();
+        for (i, rgba) in rgba_images.iter_mut().enumerate() {
+            if rgba.dimensions() != (width, height) {
+                println!("Resizing texture layer {} from {:?} to {:?}", i, rgba.dimensions(), (width, height));
+                *rgba = image::imageops::resize(rgba, width, height, image::imageops::FilterType::Nearest);
+            }
+        }
+
+        // The GL backend creates square textures with a multiple of 6 layers as cube maps, which
+        // can't be viewed as an array, so one unused layer is added to keep it an array
+        if rgba_images.len() % 6 == 0 {
+            rgba_images.push(image::RgbaImage::new(width, height));
+        }
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Block Texture Array"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: rgba_images.len() as u32,
+            },
+            mip_level_count: mip_level_count(width, height),
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        for (i, rgba) in rgba_images.iter().enumerate() {
+            let filter = if cutout.get(i).copied().unwrap_or(false) {
+                MipFilter::AlphaCoverage(CUTOUT_ALPHA_THRESHOLD)
+            } else {
+                MipFilter::Box
+            };
+            write_mip_chain(queue, &texture, i as u32, rgba, filter);
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = create_sampler(device, true);
+
+        Ok(Self { texture, view, sampler })
+    }
+}
+
+// Block textures filter anisotropically, which wgpu only allows when every filter is linear. Block
+// faces still get crisp texels up close, since the shader snaps magnified texture coordinates to
+// texel centers (shader.wgsl sample_block). The sky keeps nearest magnification.
+fn create_sampler(device: &wgpu::Device, anisotropic: bool) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: wgpu::AddressMode::MirrorRepeat,
+        address_mode_v: wgpu::AddressMode::MirrorRepeat,
+        address_mode_w: wgpu::AddressMode::MirrorRepeat,
+        mag_filter: if anisotropic { wgpu::FilterMode::Linear } else { wgpu::FilterMode::Nearest },
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        lod_min_clamp: 0.0,
+        lod_max_clamp: 100.0,
+        compare: None,
+        anisotropy_clamp: if anisotropic { 16 } else { 1 },
+        border_color: None,
+        label: Some("Texture Sampler"),
+    })
+}
+
+// Alpha below this is discarded by cutout (alpha-tested) block faces
+pub const CUTOUT_ALPHA_THRESHOLD: f32 = 0.5;
+
+#[derive(Copy, Clone)]
+enum MipFilter {
+    Box,
+    // Box filter, then rescale alpha so the same share of texels passes the cutout threshold as in level 0
+    AlphaCoverage(f32),
+}
+
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let c = value as f32 / 255.0;
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let c = if value <= 0.0031308 { value * 12.92 } else { 1.055 * value.powf(1.0 / 2.4) - 0.055 };
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
 }
 
-// Singleton for texture loading
-lazy_static::lazy_static! {
-    static ref TEXTURE: Mutex<Option<Arc<Texture>>> = Mutex::new(None);
+// Averages 2x2 blocks in linear space (the texture is sRGB), clamping at odd edges
+fn downsample(image: &image::RgbaImage) -> image::RgbaImage {
+    let width = (image.width() / 2).max(1);
+    let height = (image.height() / 2).max(1);
+    image::RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0.0f32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let sx = (x * 2 + dx).min(image.width() - 1);
+            let sy = (y * 2 + dy).min(image.height() - 1);
+            let pixel = image.get_pixel(sx, sy);
+            for channel in 0..3 {
+                sum[channel] += srgb_to_linear(pixel[channel]);
+            }
+            sum[3] += pixel[3] as f32 / 255.0;
+        }
+        image::Rgba([
+            linear_to_srgb(sum[0] / 4.0),
+            linear_to_srgb(sum[1] / 4.0),
+            linear_to_srgb(sum[2] / 4.0),
+            (sum[3] / 4.0 * 255.0).round() as u8,
+        ])
+    })
+}
+
+fn alpha_coverage(image: &image::RgbaImage, threshold: f32, scale: f32) -> f32 {
+    let passing = image.pixels()
+        .filter(|pixel| pixel[3] as f32 / 255.0 * scale >= threshold)
+        .count();
+    passing as f32 / (image.width() * image.height()) as f32
+}
+
+fn preserve_alpha_coverage(image: &mut image::RgbaImage, threshold: f32, target: f32) {
+    // Coverage only grows with the scale, so a binary search finds the closest match
+    let (mut low, mut high) = (0.0f32, 4.0f32);
+    for _ in 0..16 {
+        let middle = (low + high) / 2.0;
+        if alpha_coverage(image, threshold, middle) < target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    for pixel in image.pixels_mut() {
+        pixel[3] = (pixel[3] as f32 * high).min(255.0).round() as u8;
+    }
 }
 
-pub fn get_texture(device: &wgpu::Device, queue: &wgpu::Queue, paths: [&str; 6]) -> Arc<Texture> {
-    let mut texture = TEXTURE.lock().unwrap();
-    if texture.is_none() {
-        *texture = Some(Arc::new(Texture::from_images(device, queue, paths)));
+fn write_mip_chain(queue: &wgpu::Queue, texture: &wgpu::Texture, layer: u32, rgba: &image::RgbaImage, filter: MipFilter) {
+    let base_coverage = match filter {
+        MipFilter::AlphaCoverage(threshold) => alpha_coverage(rgba, threshold, 1.0),
+        MipFilter::Box => 0.0,
+    };
+
+    let mut level_image = rgba.clone();
+    for mip_level in 0..texture.mip_level_count() {
+        if mip_level > 0 {
+            level_image = downsample(&level_image);
+            if let MipFilter::AlphaCoverage(threshold) = filter {
+                preserve_alpha_coverage(&mut level_image, threshold, base_coverage);
+            }
+        }
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            &level_image,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * level_image.width()),
+                rows_per_image: Some(level_image.height()),
+            },
+            wgpu::Extent3d {
+                width: level_image.width(),
+                height: level_image.height(),
+                depth_or_array_layers: 1,
+            },
+        );
     }
-    texture.as_ref().unwrap().clone() // Return a clone of the Arc
 }
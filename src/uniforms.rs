@@ -1,12 +1,24 @@
-use cgmath::{Matrix4, Rad, SquareMatrix}; // Added Rad import
+use cgmath::{Matrix4, SquareMatrix, Vector3};
 use bytemuck::{Pod, Zeroable};
 use crate::camera::Camera;
+use crate::fog::FogSettings;
+use crate::lighting::SunLight;
+use crate::shadow::{cascade_matrices, SHADOW_CASCADES, SHADOW_MAP_SIZE};
 
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
 pub struct Uniforms {
     pub view_proj: [[f32; 4]; 4],
     pub model: [[f32; 4]; 4],
+    pub camera_chunk: [i32; 4],
+    pub camera_offset: [f32; 4], // w holds the chunk size
+    pub fog_color: [f32; 4],
+    pub fog_params: [f32; 4], // start, end, density, mode (0 linear, 1 exponential)
+    pub sun_direction: [f32; 4], // Towards the sun, w unused
+    pub sun_color: [f32; 4],
+    pub ambient_color: [f32; 4],
+    pub shadow_view_proj: [[[f32; 4]; 4]; SHADOW_CASCADES], // Camera-relative sun view-projection per cascade
+    pub shadow_params: [f32; 4], // texel size, normal offset, unused, unused
 }
 
 impl Uniforms {
@@ -14,6 +26,15 @@ impl Uniforms {
         Self {
             view_proj: Matrix4::identity().into(),
             model: Matrix4::identity().into(),
+            camera_chunk: [0; 4],
+            camera_offset: [0.0; 4],
+            fog_color: [0.0; 4],
+            fog_params: [0.0; 4],
+            sun_direction: [0.0, 1.0, 0.0, 0.0],
+            sun_color: [0.0; 4],
+            ambient_color: [1.0; 4],
+            shadow_view_proj: [Matrix4::identity().into(); SHADOW_CASCADES],
+            shadow_params: [1.0 / SHADOW_MAP_SIZE as f32, 0.05, 0.0, 0.0],
         }
     }
 
@@ -23,8 +44,34 @@ impl Uniforms {
     }
 
     pub fn update_view_proj(&mut self, camera: &Camera) {
-        let view = Matrix4::look_at_rh(camera.eye, camera.target, camera.up);
-        let projection = cgmath::perspective(Rad(camera.fovy), camera.aspect, camera.znear, camera.zfar);
-        self.view_proj = (projection * view).into();
+        self.view_proj = camera.build_view_projection_matrix().into();
     }
-}
\ No newline at end of file
+
+    pub fn update_camera_origin(&mut self, camera: &Camera, chunk_size: usize) {
+        let (chunk_pos, offset) = camera.chunk_and_offset(chunk_size);
+        self.camera_chunk = [chunk_pos.0, 0, chunk_pos.1, 0];
+        self.camera_offset = [offset[0], offset[1], offset[2], chunk_size as f32];
+    }
+
+    pub fn update_fog(&mut self, fog: &FogSettings, fog_end: f32) {
+        self.fog_color = [fog.color[0], fog.color[1], fog.color[2], 1.0];
+        self.fog_params = fog.params(fog_end);
+    }
+
+    // The fog follows the sky's horizon color as the day goes by
+    pub fn update_fog_color(&mut self, color: [f32; 3]) {
+        self.fog_color = [color[0], color[1], color[2], 1.0];
+    }
+
+    pub fn update_lighting(&mut self, sun: &SunLight) {
+        self.sun_direction = [sun.direction.x, sun.direction.y, sun.direction.z, 0.0];
+        self.sun_color = [sun.color[0], sun.color[1], sun.color[2], 1.0];
+        self.ambient_color = [sun.ambient[0], sun.ambient[1], sun.ambient[2], 1.0];
+    }
+
+    // Call after update_lighting, the cascades are fitted for the current sun direction
+    pub fn update_shadows(&mut self, camera: &Camera, max_distance: f32) {
+        let sun_direction = Vector3::new(self.sun_direction[0], self.sun_direction[1], self.sun_direction[2]);
+        self.shadow_view_proj = cascade_matrices(camera, sun_direction, max_distance);
+    }
+}
@@ -52,4 +52,57 @@ pub const INDICES: &[u16] = &[
     12, 13, 14, 14, 15, 12, // bottom
     16, 17, 18, 18, 19, 16, // right
     20, 21, 22, 22, 23, 20, // left
-];
\ No newline at end of file
+];
+
+// Voxel mesh vertex packed into two words:
+// data[0]: x (6 bits) | y (9 bits) | z (6 bits) | face (3 bits) | corner (2 bits) | ao (2 bits)
+// data[1]: texture layer (12 bits) | light (8 bits, skylight in the high nibble) | chunk draw slot (12 bits, filled in on upload)
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct PackedVertex {
+    pub data: [u32; 2],
+}
+
+impl PackedVertex {
+    pub fn new(position: [u32; 3], face: u32, corner: u32, ao: u32, layer: u32, light: u8) -> Self {
+        Self {
+            data: [
+                position[0] | position[1] << 6 | position[2] << 15 | face << 21 | corner << 24 | ao << 26,
+                (layer & 0xFFF) | (light as u32) << 12,
+            ],
+        }
+    }
+
+    pub fn local_position(&self) -> [u32; 3] {
+        [self.data[0] & 0x3F, (self.data[0] >> 6) & 0x1FF, (self.data[0] >> 15) & 0x3F]
+    }
+
+    pub fn set_slot(&mut self, slot: u32) {
+        self.data[1] = (self.data[1] & 0xFFFFF) | slot << 20;
+    }
+}
+
+// Unit cube corners per face in +X, -X, +Y, -Y, +Z, -Z order, counter-clockwise seen from outside.
+// Corners run bottom-left, bottom-right, top-right, top-left of the face texture.
+pub const FACE_CORNERS: [[[u32; 3]; 4]; 6] = [
+    [[1, 0, 1], [1, 0, 0], [1, 1, 0], [1, 1, 1]],
+    [[0, 0, 0], [0, 0, 1], [0, 1, 1], [0, 1, 0]],
+    [[0, 1, 1], [1, 1, 1], [1, 1, 0], [0, 1, 0]],
+    [[0, 0, 0], [1, 0, 0], [1, 0, 1], [0, 0, 1]],
+    [[0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1]],
+    [[1, 0, 0], [0, 0, 0], [0, 1, 0], [1, 1, 0]],
+];
+
+pub const FACE_NORMALS: [[i32; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+pub const FACE_INDICES: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+// The same quad split along the other diagonal, still counter-clockwise
+pub const FLIPPED_FACE_INDICES: [u16; 6] = [1, 2, 3, 3, 0, 1];
@@ -1,34 +1,144 @@
 use std::collections::HashMap;
-use crate::vertex::Vertex;
-use crate::chunk::{generate_chunk_vertices, generate_chunk_indices};
+use std::sync::Arc;
+use crate::vertex::PackedVertex;
+use crate::block::BlockRegistry;
+use crate::chunk::{generate_chunk_mesh, generate_chunk_blocks, block_index, local_block, CHUNK_HEIGHT};
+use crate::occlusion::{compute_visibility, ChunkVisibility};
+use crate::light::{light_at, light_chunk, update_block_light};
 
 pub struct Chunk {
-    pub vertices: Vec<Vertex>,
+    pub vertices: Vec<PackedVertex>,
     pub indices: Vec<u16>,
+    pub translucent_vertices: Vec<PackedVertex>,
+    pub translucent_indices: Vec<u16>,
+    pub blocks: Vec<u16>,
+    pub light: Vec<u8>, // Skylight and block light per voxel, see light.rs
+    pub visibility: ChunkVisibility,
+    pub revision: u32, // Bumped on every remesh so the chunk arena re-uploads it
 }
 
 pub struct World {
     pub chunks: HashMap<(i32, i32), Chunk>,
     pub chunk_size: usize,
+    pub registry: Arc<BlockRegistry>,
 }
 
 impl World {
-    pub fn new(chunk_size: usize) -> Self {
+    pub fn new(chunk_size: usize, registry: Arc<BlockRegistry>) -> Self {
         Self {
             chunks: HashMap::new(),
             chunk_size,
+            registry,
         }
     }
 
     pub fn load_chunk(&mut self, chunk_pos: (i32, i32)) {
         if !self.chunks.contains_key(&chunk_pos) {
-            let vertices = generate_chunk_vertices(chunk_pos, self.chunk_size);
-            let indices = generate_chunk_indices(self.chunk_size);
+            let blocks = generate_chunk_blocks(self.chunk_size, &self.registry);
+            let visibility = compute_visibility(&blocks, self.chunk_size, &self.registry);
+            let light = vec![0; blocks.len()];
 
             self.chunks.insert(chunk_pos, Chunk {
-                vertices,
-                indices,
+                vertices: Vec::new(),
+                indices: Vec::new(),
+                translucent_vertices: Vec::new(),
+                translucent_indices: Vec::new(),
+                blocks,
+                light,
+                visibility,
+                revision: 0,
             });
+
+            // Light spills over into neighbours that are already loaded, and their border AO now sees
+            // this chunk's blocks, so they are remeshed too
+            let mut dirty = light_chunk(self, chunk_pos);
+            for dx in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbour = (chunk_pos.0 + dx, chunk_pos.1 + dz);
+                    if self.chunks.contains_key(&neighbour) {
+                        dirty.insert(neighbour);
+                    }
+                }
+            }
+            for dirty in dirty {
+                self.remesh_chunk(dirty);
+            }
+        }
+    }
+
+    pub fn remesh_chunk(&mut self, chunk_pos: (i32, i32)) {
+        let Some(chunk) = self.chunks.get(&chunk_pos) else {
+            return;
+        };
+        let size = self.chunk_size as i32;
+        let origin = [chunk_pos.0 * size, 0, chunk_pos.1 * size];
+        let world_position = |local: [i32; 3]| [origin[0] + local[0], local[1], origin[2] + local[2]];
+        let mesh = generate_chunk_mesh(
+            &chunk.blocks,
+            self.chunk_size,
+            &self.registry,
+            |local| light_at(self, world_position(local)),
+            |local| self.block_at(world_position(local)).is_some_and(|id| self.registry.is_opaque(id)),
+        );
+
+        let chunk = self.chunks.get_mut(&chunk_pos).unwrap();
+        chunk.vertices = mesh.vertices;
+        chunk.indices = mesh.indices;
+        chunk.translucent_vertices = mesh.translucent_vertices;
+        chunk.translucent_indices = mesh.translucent_indices;
+        chunk.revision += 1;
+    }
+
+    // Places (or with AIR removes) a block, relighting and remeshing only the chunks it affects.
+    // Returns false when the position isn't inside a loaded chunk.
+    pub fn set_block(&mut self, position: [i32; 3], id: u16) -> bool {
+        if position[1] < 0 || position[1] >= CHUNK_HEIGHT as i32 {
+            return false;
+        }
+        let size = self.chunk_size as i32;
+        let chunk_pos = (position[0].div_euclid(size), position[2].div_euclid(size));
+        let local = [position[0].rem_euclid(size) as usize, position[1] as usize, position[2].rem_euclid(size) as usize];
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos) else {
+            return false;
+        };
+        let index = block_index(local[0], local[1], local[2], self.chunk_size);
+        if chunk.blocks[index] == id {
+            return true;
         }
+        chunk.blocks[index] = id;
+        chunk.visibility = compute_visibility(&chunk.blocks, self.chunk_size, &self.registry);
+
+        let mut dirty = update_block_light(self, position);
+        // Neighbouring chunks read blocks across the border for their AO
+        let border_x = |dx: i32| dx == 0 || (dx < 0 && local[0] == 0) || (dx > 0 && local[0] == self.chunk_size - 1);
+        let border_z = |dz: i32| dz == 0 || (dz < 0 && local[2] == 0) || (dz > 0 && local[2] == self.chunk_size - 1);
+        for dx in -1..=1 {
+            for dz in -1..=1 {
+                let neighbour = (chunk_pos.0 + dx, chunk_pos.1 + dz);
+                if border_x(dx) && border_z(dz) && self.chunks.contains_key(&neighbour) {
+                    dirty.insert(neighbour);
+                }
+            }
+        }
+        for dirty in dirty {
+            self.remesh_chunk(dirty);
+        }
+        true
+    }
+
+    // Block id at a world block position, None when its chunk isn't loaded or it's outside the world height
+    pub fn block_at(&self, position: [i32; 3]) -> Option<u16> {
+        if position[1] < 0 || position[1] >= CHUNK_HEIGHT as i32 {
+            return None;
+        }
+        let size = self.chunk_size as i32;
+        let chunk_pos = (position[0].div_euclid(size), position[2].div_euclid(size));
+        let local = [position[0].rem_euclid(size), position[1], position[2].rem_euclid(size)];
+        self.chunks.get(&chunk_pos).and_then(|chunk| local_block(&chunk.blocks, local, self.chunk_size))
+    }
+
+    // Collision query against the registry's solidity
+    pub fn is_solid_at(&self, position: [i32; 3]) -> bool {
+        self.block_at(position).is_some_and(|id| self.registry.is_solid(id))
     }
 }
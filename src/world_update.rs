@@ -1,15 +1,23 @@
 use crate::camera::Camera;
 use crate::world::World;
 
+// Chunks are loaded this many chunks out from the camera's chunk in each direction
+pub const LOAD_RADIUS: i32 = 1;
+
+// The closest the edge of the loaded area can get to the camera, which is as far as terrain can be trusted to exist
+pub fn view_distance(chunk_size: usize) -> f32 {
+    (LOAD_RADIUS * chunk_size as i32) as f32
+}
+
 pub fn update_world(camera: &Camera, world: &mut World) {
     let current_chunk_pos = (
         (camera.eye.x / (world.chunk_size as f32)).floor() as i32,
         (camera.eye.z / (world.chunk_size as f32)).floor() as i32,
     );
 
-    // Load chunks in a 3x3 area around the current chunk position
-    for dx in -1..=1 {
-        for dz in -1..=1 {
+    // Load a square of (2 * LOAD_RADIUS + 1)² chunks centred on the current chunk
+    for dx in -LOAD_RADIUS..=LOAD_RADIUS {
+        for dz in -LOAD_RADIUS..=LOAD_RADIUS {
             world.load_chunk((current_chunk_pos.0 + dx, current_chunk_pos.1 + dz));
         }
     }